@@ -0,0 +1,184 @@
+use std::{
+    env, fs,
+    hint::black_box,
+    path::{Path, PathBuf},
+};
+
+use criterion::{criterion_group, BatchSize, BenchmarkId, Criterion};
+use procedural_map::{
+    algorithms::Algorithm,
+    map::{Map, TileType},
+    map_builders::{remove_unreachable_areas_returning_most_distant, SnapshotPolicy},
+};
+use serde_json::Value;
+
+const SIZES: [(i32, i32); 3] = [(60, 40), (256, 256), (1024, 1024)];
+const SEED: u64 = 42;
+
+/// Maps bigger than this are only built without snapshots, every snapshot copies the whole map.
+const MAX_SNAPSHOT_TILES: i32 = 256 * 256;
+
+fn builders(c: &mut Criterion) {
+    for (width, height) in SIZES {
+        let mut group = c.benchmark_group(format!("build {}x{}", width, height));
+        if width * height > MAX_SNAPSHOT_TILES {
+            group.sample_size(10);
+        }
+
+        for algorithm in Algorithm::all() {
+            for snapshots in [SnapshotPolicy::EveryStep, SnapshotPolicy::Off] {
+                if snapshots != SnapshotPolicy::Off && width * height > MAX_SNAPSHOT_TILES {
+                    continue;
+                }
+                let label = match snapshots {
+                    SnapshotPolicy::Off => "no snapshots",
+                    _ => "snapshots",
+                };
+                let id = BenchmarkId::new(algorithm.key(), label);
+                group.bench_function(id, |b| {
+                    b.iter(|| {
+                        let mut builder = algorithm.get(&algorithm.options()).unwrap();
+                        builder.set_snapshot_policy(snapshots);
+                        builder
+                            .build_map(width, height, black_box(SEED))
+                            .expect("Default options build a map");
+                        builder
+                    })
+                });
+            }
+        }
+        group.finish();
+    }
+}
+
+/// Cave map without snapshots, the one with the most unreachable areas.
+fn cave(width: i32, height: i32) -> Map {
+    let algorithm = Algorithm::CellularAutomata;
+    let mut builder = algorithm.get(&algorithm.options()).unwrap();
+    builder.set_snapshot_policy(SnapshotPolicy::Off);
+    builder
+        .build_map(width, height, SEED)
+        .expect("Default options build a map");
+    builder.get_map()
+}
+
+fn reachability(c: &mut Criterion) {
+    let mut group = c.benchmark_group("remove unreachable areas");
+    for (width, height) in SIZES {
+        let map = cave(width, height);
+        let start = map.start.expect("Caves have a start");
+        let id = BenchmarkId::from_parameter(format!("{}x{}", width, height));
+        group.bench_function(id, |b| {
+            b.iter_batched(
+                || map.clone(),
+                |mut map| remove_unreachable_areas_returning_most_distant(&mut map, start),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn autotiling(c: &mut Criterion) {
+    let mut group = c.benchmark_group("wall texture");
+    for (width, height) in SIZES {
+        let map = cave(width, height);
+        let walls: Vec<(i32, i32)> = (0..map.tiles.len())
+            .filter(|idx| map.tiles[*idx] == Some(TileType::Wall))
+            .map(|idx| map.idx_xy(idx))
+            .collect();
+        let id = BenchmarkId::from_parameter(format!("{}x{}", width, height));
+        group.bench_function(id, |b| {
+            b.iter(|| {
+                walls
+                    .iter()
+                    .map(|(x, y)| map.wall_texture(*x, *y))
+                    .sum::<usize>()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, builders, reachability, autotiling);
+
+fn main() {
+    benches();
+    Criterion::default().configure_from_args().final_summary();
+
+    let dir = env::var_os("CRITERION_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("CARGO_TARGET_DIR").map(|dir| PathBuf::from(dir).join("criterion")))
+        .unwrap_or_else(|| PathBuf::from("target/criterion"));
+    // Nothing is saved when only testing the benchmarks
+    if !dir.is_dir() {
+        return;
+    }
+    match write_comparison(&dir) {
+        Ok(path) => println!("Comparison table written to {}", path.display()),
+        Err(err) => eprintln!("Could not write the comparison table: {}", err),
+    }
+}
+
+/// Writes the mean time of every benchmark and its change since the baseline as a Markdown
+/// table.
+fn write_comparison(dir: &Path) -> std::io::Result<PathBuf> {
+    let mut results = vec![];
+    collect_results(dir, &mut results)?;
+    results.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut table = "| Benchmark | Mean | Change |\n|---|---:|---:|\n".to_string();
+    for (name, mean, change) in results {
+        let change = change.map_or("-".to_string(), |change| format!("{:+.1}%", change * 100.));
+        table += &format!("| {} | {} | {} |\n", name, format_time(mean), change);
+    }
+
+    let path = dir.join("comparison.md");
+    fs::write(&path, table)?;
+    Ok(path)
+}
+
+/// Finds every benchmark result below `dir` along with its relative change, if it was compared.
+fn collect_results(
+    dir: &Path,
+    results: &mut Vec<(String, f64, Option<f64>)>,
+) -> std::io::Result<()> {
+    let new = dir.join("new");
+    if new.join("estimates.json").exists() {
+        let benchmark = read_json(&new.join("benchmark.json"));
+        let estimates = read_json(&new.join("estimates.json"));
+        let change = read_json(&dir.join("change/estimates.json"));
+        if let (Some(name), Some(mean)) = (
+            benchmark["full_id"].as_str(),
+            estimates["mean"]["point_estimate"].as_f64(),
+        ) {
+            let change = change["mean"]["point_estimate"].as_f64();
+            results.push((name.to_string(), mean, change));
+        }
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_results(&path, results)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_json(path: &Path) -> Value {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or(Value::Null)
+}
+
+fn format_time(nanoseconds: f64) -> String {
+    match nanoseconds {
+        t if t < 1e3 => format!("{:.1} ns", t),
+        t if t < 1e6 => format!("{:.1} µs", t / 1e3),
+        t if t < 1e9 => format!("{:.1} ms", t / 1e6),
+        t => format!("{:.2} s", t / 1e9),
+    }
+}
@@ -1,6 +1,8 @@
-use std::fmt;
+use std::{fmt, str::FromStr};
 
-use crate::{map::Map, map_builders::*};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::map_builders::*;
 
 #[derive(Default, PartialEq, Eq, Copy, Clone)]
 pub enum Algorithm {
@@ -28,6 +30,32 @@ impl fmt::Display for Algorithm {
     }
 }
 
+impl FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Algorithm::all()
+            .into_iter()
+            .find(|alg| alg.key() == s)
+            .ok_or_else(|| format!("Unknown algorithm '{}'", s))
+    }
+}
+
+/// Algorithms are stored by their key.
+impl Serialize for Algorithm {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.key())
+    }
+}
+
+impl<'de> Deserialize<'de> for Algorithm {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
 impl Algorithm {
     pub fn all() -> Vec<Self> {
         vec![
@@ -39,24 +67,43 @@ impl Algorithm {
             Algorithm::Drunkard,
         ]
     }
-    pub fn get(&self) -> Box<dyn MapBuilder> {
+    /// Builder of the algorithm using the given option values, an error if no algorithm is
+    /// selected.
+    pub fn get(&self, options: &[Option]) -> Result<Box<dyn MapBuilder>, GenerationError> {
+        let builder: Box<dyn MapBuilder> = match self {
+            Algorithm::Random => Box::new(RandomMap::new(RandomSettings::from_options(options))),
+            Algorithm::Rooms => Box::new(RoomsMap::new(RoomsSettings::from_options(options))),
+            Algorithm::Bsp => Box::new(BspMap::new(BspSettings::from_options(options))),
+            Algorithm::BspInterior => Box::new(BspInteriorMap::new(
+                BspInteriorSettings::from_options(options),
+            )),
+            Algorithm::CellularAutomata => Box::new(CellularAutomataBuilder::new(
+                CellularAutomataSettings::from_options(options),
+            )),
+            Algorithm::Drunkard => Box::new(DrunkardsWalkBuilder::new(
+                DrunkardSettings::from_options(options),
+            )),
+            Algorithm::None => return Err(GenerationError::NoAlgorithm),
+        };
+        Ok(builder)
+    }
+
+    /// Short identifier used on the command line.
+    pub fn key(&self) -> &'static str {
         match self {
-            Algorithm::Random => Box::new(RandomMap::default()),
-            Algorithm::Rooms => Box::new(RoomsMap::default()),
-            Algorithm::Bsp => Box::new(BspMap::default()),
-            Algorithm::BspInterior => Box::new(BspInteriorMap::default()),
-            Algorithm::CellularAutomata => Box::new(CellularAutomataBuilder::default()),
-            Algorithm::Drunkard => Box::new(DrunkardsWalkBuilder::new(DrunkardSettings {
-                spawn_mode: DrunkSpawnMode::Random,
-                drunken_lifetime: 100,
-                floor_percent: 0.4,
-            })),
-            _ => panic!("No algorithm selected"),
+            Algorithm::None => "none",
+            Algorithm::Random => "random",
+            Algorithm::Rooms => "rooms",
+            Algorithm::Bsp => "bsp",
+            Algorithm::BspInterior => "bsp-interior",
+            Algorithm::CellularAutomata => "cellular-automata",
+            Algorithm::Drunkard => "drunkard",
         }
     }
 
-    pub fn description(&self) -> &'static str {
-        match self {
+    /// What the algorithm does, `None` if no algorithm is selected.
+    pub fn description(&self) -> std::option::Option<&'static str> {
+        let description = match self {
             Algorithm::Random => "Place walls at the edges and randomly elsewhere",
             Algorithm::Rooms => "Place random rooms and connect them with corridors",
             Algorithm::Bsp => {
@@ -67,81 +114,98 @@ impl Algorithm {
             },
             Algorithm::CellularAutomata => "Use cellular automata to generate the map",
             Algorithm::Drunkard => "Use a drunkard's walk to generate the map",
-            _ => panic!("No algorithm selected"),
+            Algorithm::None => return None,
+        };
+        Some(description)
+    }
+
+    /// Whether the builder repairs the regions its walls leave disconnected, builders joining
+    /// all their rooms with corridors ignore the region repair.
+    pub fn repairs_regions(&self) -> bool {
+        matches!(
+            self,
+            Algorithm::Random | Algorithm::CellularAutomata | Algorithm::Drunkard
+        )
+    }
+
+    /// Default options of the algorithm.
+    pub fn options(&self) -> Vec<Option> {
+        match self {
+            Algorithm::Random => RandomSettings::options(),
+            Algorithm::Rooms => RoomsSettings::options(),
+            Algorithm::Bsp => BspSettings::options(),
+            Algorithm::BspInterior => BspInteriorSettings::options(),
+            Algorithm::CellularAutomata => CellularAutomataSettings::options(),
+            Algorithm::Drunkard => DrunkardSettings::options(),
+            _ => vec![],
         }
     }
 
-    pub fn options(&self) -> &[Option] {
+    /// Returns every reason the options can't be used, empty if they're valid.
+    pub fn validate(&self, options: &[Option]) -> Vec<String> {
         match self {
-            Algorithm::Random => &[Option {
-                name: "Ratio",
-                value: 4,
-                min: 1,
-                max: 10,
-            }],
-            Algorithm::Rooms => &[
-                Option {
-                    name: "Max rooms",
-                    value: 5,
-                    min: 1,
-                    max: 30,
-                },
-                Option {
-                    name: "Min room size",
-                    value: 4,
-                    min: 6,
-                    max: 10,
-                },
-                Option {
-                    name: "Max room size",
-                    value: 10,
-                    min: 10,
-                    max: 15,
-                },
-            ],
-            Algorithm::Bsp => &[Option {
-                name: "Max rooms",
-                value: 240,
-                min: 1,
-                max: 350,
-            }],
-            Algorithm::BspInterior => &[
-                Option {
-                    name: "Min room size",
-                    value: 6,
-                    min: 6,
-                    max: 100,
-                },
-                Option {
-                    name: "Vertical split percentage",
-                    value: 50,
-                    min: 1,
-                    max: 100,
-                },
-            ],
-            Algorithm::CellularAutomata => &[
-                Option {
-                    name: "Floor percentage",
-                    value: 55,
-                    min: 1,
-                    max: 100,
-                },
-                Option {
-                    name: "Iterations",
-                    value: 15,
-                    min: 1,
-                    max: 100,
-                },
-            ],
-            _ => &[],
+            Algorithm::Random => RandomSettings::validate(options),
+            Algorithm::Rooms => RoomsSettings::validate(options),
+            Algorithm::Bsp => BspSettings::validate(options),
+            Algorithm::BspInterior => BspInteriorSettings::validate(options),
+            Algorithm::CellularAutomata => CellularAutomataSettings::validate(options),
+            Algorithm::Drunkard => DrunkardSettings::validate(options),
+            _ => vec![],
         }
     }
 }
 
+/// Value of a builder setting along with its declaration.
 #[derive(Clone)]
 pub struct Option {
+    /// Stable identifier of the setting, used in presets and links.
+    pub key: &'static str,
     pub name: &'static str,
     pub value: i32,
     pub min: i32,
     pub max: i32,
+    pub unit: Unit,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn algorithms_parse_from_their_key() {
+        for algorithm in Algorithm::all() {
+            assert!(algorithm.key().parse() == Ok(algorithm));
+        }
+        assert!("none".parse::<Algorithm>() == Err("Unknown algorithm 'none'".to_string()));
+        assert!("BSP".parse::<Algorithm>().is_err());
+        assert!("".parse::<Algorithm>().is_err());
+    }
+
+    #[test]
+    fn no_algorithm_has_no_builder() {
+        for algorithm in Algorithm::all() {
+            assert!(algorithm.get(&algorithm.options()).is_ok());
+            assert!(algorithm.description().is_some());
+        }
+        assert_eq!(
+            Algorithm::None.get(&[]).err(),
+            Some(GenerationError::NoAlgorithm)
+        );
+        assert!(Algorithm::None.description().is_none());
+    }
+
+    #[test]
+    fn only_builders_with_a_repair_repair_regions() {
+        // Culling records its phase even when every tile is reachable
+        for algorithm in Algorithm::all() {
+            let mut builder = algorithm.get(&algorithm.options()).unwrap();
+            builder.set_region_repair(RegionRepair::KeepReachable);
+            builder.build_map(60, 40, 1).unwrap();
+            let culled = builder
+                .get_snapshot_history()
+                .iter()
+                .any(|snapshot| snapshot.phase == "cull unreachable");
+            assert_eq!(culled, algorithm.repairs_regions(), "{}", algorithm);
+        }
+    }
 }
@@ -0,0 +1,120 @@
+use std::collections::VecDeque;
+
+use bracket_pathfinding::prelude::{a_star_search, BaseMap, DijkstraMap};
+
+use crate::map::Map;
+
+pub const ORTHOGONAL: [(i32, i32); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+pub const DIAGONAL: [(i32, i32); 4] = [(-1, -1), (1, -1), (-1, 1), (1, 1)];
+
+/// Distance of every tile to the start of the map and the path from start to exit.
+#[derive(Default, Clone)]
+pub struct Analysis {
+    /// Distance of every tile to the start, `f32::MAX` if unreachable.
+    pub distances: Vec<f32>,
+    /// Tiles from start to exit, both included. Empty if the exit can't be reached.
+    pub path: Vec<usize>,
+    /// Cost of walking the path, diagonal steps cost more than cardinal ones. The number of steps
+    /// is the `path_length` of the metrics.
+    pub path_cost: f32,
+}
+
+impl Analysis {
+    /// Analyses a map, returns `None` if the map has no start or exit.
+    pub fn new(map: &Map) -> Option<Self> {
+        let start = map.start?;
+        let exit = map.exit?;
+
+        let distances = distance_map(map, start);
+        let path = find_path(map, start, exit);
+        let path_cost = path
+            .windows(2)
+            .map(|step| {
+                map.get_available_exits(step[0])
+                    .iter()
+                    .find(|(idx, _)| *idx == step[1])
+                    .map_or(1.0, |(_, cost)| *cost)
+            })
+            .sum();
+
+        Some(Self {
+            distances,
+            path,
+            path_cost,
+        })
+    }
+}
+
+/// Returns the distance of every tile to `start`, `f32::MAX` if unreachable.
+pub fn distance_map(map: &Map, start: usize) -> Vec<f32> {
+    DijkstraMap::new(
+        map.width as usize,
+        map.height as usize,
+        &[start],
+        map,
+        (map.width * map.height) as f32,
+    )
+    .map
+}
+
+/// Returns the reachable tile further away from the start.
+pub fn most_distant(distances: &[f32]) -> Option<usize> {
+    distances
+        .iter()
+        .enumerate()
+        .filter(|(_, d)| **d < f32::MAX)
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(idx, _)| idx)
+}
+
+/// Returns the tiles from `start` to `end`, empty if there's no path.
+pub fn find_path(map: &Map, start: usize, end: usize) -> Vec<usize> {
+    if start == end {
+        return vec![start];
+    }
+    let path = a_star_search(start, end, map);
+    if path.success {
+        path.steps
+    } else {
+        vec![]
+    }
+}
+
+/// Returns the tiles of every connected walkable region, biggest first.
+pub fn regions(map: &Map, diagonals: bool) -> Vec<Vec<usize>> {
+    let directions = if diagonals {
+        [ORTHOGONAL, DIAGONAL].concat()
+    } else {
+        ORTHOGONAL.to_vec()
+    };
+
+    let mut visited = vec![false; map.tiles.len()];
+    let mut regions = vec![];
+    for start in 0..map.tiles.len() {
+        if !map.is_walkable(start) || visited[start] {
+            continue;
+        }
+
+        let mut region = vec![];
+        let mut queue = VecDeque::from([start]);
+        visited[start] = true;
+        while let Some(idx) = queue.pop_front() {
+            region.push(idx);
+            let (x, y) = map.idx_xy(idx);
+            for (nx, ny) in directions.iter().map(|(dx, dy)| (x + dx, y + dy)) {
+                if nx < 0 || nx >= map.width || ny < 0 || ny >= map.height {
+                    continue;
+                }
+                let next = map.xy_idx(nx, ny);
+                if map.is_walkable(next) && !visited[next] {
+                    visited[next] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+        regions.push(region);
+    }
+
+    regions.sort_by_key(|region| std::cmp::Reverse(region.len()));
+    regions
+}
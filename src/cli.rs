@@ -0,0 +1,187 @@
+use procedural_map::{
+    algorithms::Algorithm,
+    export::export_animation,
+    generator::{generate, Fitness, GenerationRequest},
+    map_builders::{RegionRepair, SnapshotPolicy},
+    metrics::MapMetrics,
+    preset::Preset,
+};
+use serde_json::json;
+use std::{collections::BTreeMap, path::Path};
+
+const USAGE: &str =
+    "Usage: procedural-map [--preset <file>] [--algorithm <name>] [--width <n>] [--height <n>]
+    [--seed <n>] [--repair join|reachable|drop:<min size>] [--options <key=value,...>]
+    [--animation <file.gif|file.png>] [--delay <ms>] [--snapshots off|all|phases|every:<n>]
+
+Generates a map without opening a window and prints its metrics as JSON.
+Arguments given after a preset override its values.
+With --animation the build steps are also written as a GIF or an animated PNG.
+Algorithms: random, rooms, bsp, bsp-interior, cellular-automata, drunkard";
+
+/// Map generation requested from the command line.
+struct Args {
+    algorithm: Algorithm,
+    width: i32,
+    height: i32,
+    options: BTreeMap<String, i32>,
+    seed: u64,
+    repair: RegionRepair,
+    /// File the build steps are animated to.
+    animation: Option<String>,
+    /// Time every frame of the animation is shown, in milliseconds.
+    delay: u64,
+    snapshots: Option<SnapshotPolicy>,
+}
+
+impl Args {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut parsed = Args {
+            algorithm: Algorithm::None,
+            width: 60,
+            height: 40,
+            options: BTreeMap::new(),
+            seed: rand::random(),
+            repair: RegionRepair::default(),
+            animation: None,
+            delay: 100,
+            snapshots: None,
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for {}", arg))
+            };
+            match arg.as_str() {
+                "--preset" => {
+                    let preset = Preset::load(Path::new(value()?))?;
+                    parsed.algorithm = preset.algorithm;
+                    parsed.width = preset.width;
+                    parsed.height = preset.height;
+                    parsed.options = preset.options;
+                    parsed.repair = preset.repair;
+                    if let Some(seed) = preset.seed {
+                        parsed.seed = seed;
+                    }
+                }
+                "--algorithm" => parsed.algorithm = value()?.parse()?,
+                "--width" => parsed.width = parse_number(value()?)?,
+                "--height" => parsed.height = parse_number(value()?)?,
+                "--seed" => {
+                    let seed = value()?;
+                    parsed.seed = seed
+                        .parse()
+                        .map_err(|_| format!("Invalid seed '{}'", seed))?
+                }
+                "--repair" => parsed.repair = value()?.parse()?,
+                "--animation" => parsed.animation = Some(value()?.clone()),
+                "--delay" => {
+                    let delay = value()?;
+                    parsed.delay = delay
+                        .parse()
+                        .map_err(|_| format!("Invalid delay '{}'", delay))?
+                }
+                "--snapshots" => parsed.snapshots = Some(value()?.parse()?),
+                "--options" => {
+                    for pair in value()?.split(',') {
+                        let (key, value) = pair.split_once('=').ok_or_else(|| {
+                            format!("Invalid option '{}', expected key=value", pair)
+                        })?;
+                        parsed
+                            .options
+                            .insert(key.trim().to_string(), parse_number(value)?);
+                    }
+                }
+                _ => return Err(format!("Unknown argument {}", arg)),
+            }
+        }
+
+        if parsed.algorithm == Algorithm::None {
+            return Err("No algorithm selected".to_string());
+        }
+        Ok(parsed)
+    }
+}
+
+fn parse_number(value: &str) -> Result<i32, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid number '{}'", value))
+}
+
+/// Runs the headless generator and returns the process exit code.
+pub fn run(args: &[String]) -> i32 {
+    let args = match Args::parse(args) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            return 1;
+        }
+    };
+
+    // Options not given on the command line keep their default value
+    let mut options = args.algorithm.options();
+    for (key, value) in args.options.iter() {
+        match options.iter_mut().find(|option| option.key == key) {
+            Some(option) => option.value = *value,
+            None => {
+                let keys: Vec<&str> = options.iter().map(|option| option.key).collect();
+                eprintln!(
+                    "Unknown option '{}' for {}, expected one of: {}",
+                    key,
+                    args.algorithm,
+                    keys.join(", ")
+                );
+                return 1;
+            }
+        }
+    }
+
+    let generation = match generate(&GenerationRequest {
+        algorithm: args.algorithm,
+        width: args.width,
+        height: args.height,
+        options: options.clone(),
+        repair: args.repair,
+        seed: args.seed,
+        candidates: 1,
+        fitness: Fitness::default(),
+        constraints: vec![],
+        max_attempts: 1,
+        // Snapshots are only needed to animate the build
+        snapshots: args.snapshots.unwrap_or(match args.animation {
+            Some(_) => SnapshotPolicy::EveryStep,
+            None => SnapshotPolicy::Off,
+        }),
+    }) {
+        Ok(generation) => generation,
+        Err(err) => {
+            eprintln!("{}", err);
+            return 1;
+        }
+    };
+    let builder = generation.builder;
+    if let Some(path) = args.animation.as_ref() {
+        let snapshots = Vec::from(builder.get_snapshot_history());
+        if let Err(err) = export_animation(&snapshots, args.delay, Path::new(path)) {
+            eprintln!("{}", err);
+            return 1;
+        }
+    }
+    let map = builder.get_map();
+    let metrics = MapMetrics::new(&map, builder.get_rooms().as_deref());
+
+    let output = json!({
+        "algorithm": args.algorithm.key(),
+        "width": args.width,
+        "height": args.height,
+        "options": Preset::option_values(&options),
+        "seed": args.seed,
+        "metrics": metrics,
+    });
+    println!("{}", output);
+    0
+}
@@ -16,9 +16,9 @@ impl Plugin for DebugPlugin {
             app.add_system(debug_current_state)
                 .add_plugin(WorldInspectorPlugin::new())
                 // Get frame time diagnostics
-                .add_plugin(FrameTimeDiagnosticsPlugin::default())
+                .add_plugin(FrameTimeDiagnosticsPlugin)
                 // Get entity count diagnostics
-                .add_plugin(EntityCountDiagnosticsPlugin::default())
+                .add_plugin(EntityCountDiagnosticsPlugin)
                 // Add the diagnostics window
                 .add_system(diagnostic_window);
         }
@@ -0,0 +1,348 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use rand::{rngs::StdRng, SeedableRng};
+use serde_json::{json, Value};
+
+use crate::{
+    analysis::{distance_map, regions},
+    generator::{generate, Generation, GenerationRequest},
+    map::{Map, TileType},
+    map_builders::{connect_tile, place_exit, GenerationError},
+    preset::Preset,
+    puzzle::place_locks,
+    room_graph::RoomGraph,
+    spawner::{spawn, spawn_areas, SpawnTable},
+};
+
+/// A generated level and the request it was built from.
+pub struct Level {
+    pub request: GenerationRequest,
+    pub generation: Generation,
+}
+
+/// A stack of levels, the down stairs of every level sit above the up stairs of the next one.
+pub struct Dungeon {
+    pub levels: Vec<Level>,
+}
+
+impl Dungeon {
+    /// Generates a level for every request, the first one being the top of the dungeon.
+    pub fn generate(requests: &[GenerationRequest]) -> Result<Self, GenerationError> {
+        let mut levels = vec![];
+        for request in requests {
+            levels.push(Level {
+                request: request.clone(),
+                generation: generate(request)?,
+            });
+        }
+
+        for depth in 1..levels.len() {
+            let (upper, lower) = levels.split_at_mut(depth);
+            connect_levels(
+                upper[depth - 1].generation.builder.get_map_mut(),
+                lower[0].generation.builder.get_map_mut(),
+            );
+            let stairs = [
+                (
+                    &mut upper[depth - 1].generation.builder,
+                    "place down stairs",
+                ),
+                (&mut lower[0].generation.builder, "place up stairs"),
+            ];
+            for (builder, label) in stairs {
+                builder.start_phase("connect levels");
+                builder.take_snapshot(label);
+                builder.end_phase();
+            }
+        }
+
+        Ok(Self { levels })
+    }
+
+    /// Places up to `max_per_area` spawns in every room or area of every level, deeper levels
+    /// rolling the table with their own depth.
+    pub fn spawn(&mut self, table: &SpawnTable, max_per_area: usize) {
+        for (depth, level) in self.levels.iter_mut().enumerate() {
+            let builder = &mut level.generation.builder;
+            let rooms = builder.get_rooms();
+            let map = builder.get_map_mut();
+            let areas = spawn_areas(map, rooms.as_deref());
+            let mut rng = StdRng::seed_from_u64(level.generation.seed);
+            map.spawns = spawn(map, &areas, table, depth as i32 + 1, max_per_area, &mut rng);
+            let note = format!("{} spawns in {} areas", map.spawns.len(), areas.len());
+
+            builder.start_phase("spawn");
+            builder.add_note(&note);
+            builder.take_snapshot("place spawns");
+            builder.end_phase();
+        }
+    }
+
+    /// Locks up to `max_locks` doors on the way to the exit of every level, between the rooms of
+    /// its builder or the ones found in caves, the key of each door lying before it.
+    pub fn lock(&mut self, max_locks: usize) {
+        for level in self.levels.iter_mut() {
+            let builder = &mut level.generation.builder;
+            let rooms = builder.get_rooms();
+            let map = builder.get_map_mut();
+            let graph = RoomGraph::new(map, rooms.as_deref());
+            let mut rng = StdRng::seed_from_u64(level.generation.seed);
+            let count = place_locks(map, &graph, max_locks, &mut rng);
+
+            builder.start_phase("lock and key");
+            builder.add_note(&format!("{} locked doors", count));
+            builder.take_snapshot("place locks");
+            builder.end_phase();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    /// Writes the JSON of every level to `dir`, in a file named after the seed of the first level.
+    pub fn save(&self, dir: &Path) -> Result<PathBuf, String> {
+        let seed = self.levels.first().map_or(0, |level| level.generation.seed);
+        let path = dir.join(format!("dungeon-{}.json", seed));
+        let content =
+            serde_json::to_string_pretty(&self.to_json()).expect("Dungeons serialize to JSON");
+        fs::write(&path, content)
+            .map_err(|err| format!("Could not write {}: {}", path.display(), err))?;
+        Ok(path)
+    }
+
+    /// Every level with its settings and tiles, rows of tiles are written as text.
+    pub fn to_json(&self) -> Value {
+        let levels: Vec<Value> = self
+            .levels
+            .iter()
+            .enumerate()
+            .map(|(depth, level)| {
+                let map = level.generation.builder.get_map();
+                json!({
+                    "depth": depth,
+                    "algorithm": level.request.algorithm.key(),
+                    "width": map.width,
+                    "height": map.height,
+                    "seed": level.generation.seed,
+                    "options": Preset::option_values(&level.request.options),
+                    "start": map.start.map(|idx| map.idx_xy(idx)),
+                    "exit": map.exit.map(|idx| map.idx_xy(idx)),
+                    "tiles": map.rows(),
+                    "spawns": map.spawns.iter().map(|spawn| {
+                        let (x, y) = map.idx_xy(spawn.idx);
+                        json!({ "x": x, "y": y, "name": spawn.name, "kind": spawn.kind })
+                    }).collect::<Vec<_>>(),
+                    "locks": map.locks.iter().map(|lock| {
+                        json!({ "door": map.idx_xy(lock.door), "key": map.idx_xy(lock.key) })
+                    }).collect::<Vec<_>>(),
+                })
+            })
+            .collect();
+        json!({ "levels": levels })
+    }
+}
+
+/// Turns a tile of the upper level into down stairs and the tile at the same position of the
+/// lower level into its up stairs.
+///
+/// The stairs go on the position closest to the exit of the upper level that is free floor on
+/// both levels, reachable from the start of the upper level and in the biggest region of the
+/// lower one. Levels without floor in common get the stairs dug out at the closest position
+/// inside both of them instead.
+fn connect_levels(upper: &mut Map, lower: &mut Map) {
+    let width = upper.width.min(lower.width);
+    let height = upper.height.min(lower.height);
+    if width < 3 || height < 3 {
+        return;
+    }
+
+    let (target_x, target_y) = upper
+        .exit
+        .map_or((width / 2, height / 2), |idx| upper.idx_xy(idx));
+    let distance = |(x, y): &(i32, i32)| (x - target_x).pow(2) + (y - target_y).pow(2);
+    let inside = || (1..height - 1).flat_map(move |y| (1..width - 1).map(move |x| (x, y)));
+
+    let reached = upper.start.map(|start| distance_map(upper, start));
+    let biggest: HashSet<usize> = regions(lower, true)
+        .into_iter()
+        .next()
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+    let is_free =
+        |map: &Map, idx: usize| map.tiles[idx] == Some(TileType::Floor) && Some(idx) != map.start;
+    let shared = inside()
+        .filter(|(x, y)| {
+            let (down, up) = (upper.xy_idx(*x, *y), lower.xy_idx(*x, *y));
+            is_free(upper, down)
+                && reached
+                    .as_ref()
+                    .is_none_or(|distances| distances[down] < f32::MAX)
+                && is_free(lower, up)
+                && biggest.contains(&up)
+        })
+        .min_by_key(distance);
+    // The up stairs of the upper level are never replaced
+    let dug = || {
+        inside()
+            .filter(|(x, y)| Some(upper.xy_idx(*x, *y)) != upper.start)
+            .min_by_key(distance)
+    };
+    let (x, y) = match shared.or_else(dug) {
+        Some(position) => position,
+        None => return,
+    };
+
+    let down = upper.xy_idx(x, y);
+    connect_tile(upper, down);
+    upper.tiles[down] = Some(TileType::DownStairs);
+    upper.exit = Some(down);
+
+    let up = lower.xy_idx(x, y);
+    connect_tile(lower, up);
+    lower.tiles[up] = Some(TileType::UpStairs);
+    lower.start = Some(up);
+    place_exit(lower);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        algorithms::Algorithm,
+        generator::Fitness,
+        map_builders::{RegionRepair, SnapshotPolicy},
+    };
+
+    /// Map of `width` by `height` tiles, floor inside a wall border.
+    fn open_map(width: i32, height: i32) -> Map {
+        let mut map = Map::new(width, height);
+        for idx in 0..map.tiles.len() {
+            let (x, y) = map.idx_xy(idx);
+            let border = x == 0 || y == 0 || x == width - 1 || y == height - 1;
+            map.tiles[idx] = Some(if border {
+                TileType::Wall
+            } else {
+                TileType::Floor
+            });
+        }
+        map
+    }
+
+    /// Positions of the down stairs of `upper` and the up stairs of `lower`.
+    fn stairs(upper: &Map, lower: &Map) -> ((i32, i32), (i32, i32)) {
+        let down = upper.exit.expect("Down stairs are the exit");
+        let up = lower.start.expect("Up stairs are the start");
+        assert_eq!(upper.tiles[down], Some(TileType::DownStairs));
+        assert_eq!(lower.tiles[up], Some(TileType::UpStairs));
+        (upper.idx_xy(down), lower.idx_xy(up))
+    }
+
+    #[test]
+    fn stairs_stay_on_the_exit_when_both_levels_have_floor_there() {
+        let (mut upper, mut lower) = (open_map(20, 12), open_map(20, 12));
+        upper.start = Some(upper.xy_idx(2, 2));
+        upper.exit = Some(upper.xy_idx(15, 8));
+        connect_levels(&mut upper, &mut lower);
+        assert_eq!(stairs(&upper, &lower), ((15, 8), (15, 8)));
+    }
+
+    #[test]
+    fn stairs_of_a_smaller_lower_level_never_replace_the_up_stairs() {
+        let (mut upper, mut lower) = (open_map(30, 12), open_map(8, 12));
+        // Clamping the exit inside the lower level would land on the start
+        let start = upper.xy_idx(6, 5);
+        upper.start = Some(start);
+        upper.tiles[start] = Some(TileType::UpStairs);
+        upper.exit = Some(upper.xy_idx(25, 5));
+        connect_levels(&mut upper, &mut lower);
+
+        let (down, up) = stairs(&upper, &lower);
+        assert_eq!(down, up);
+        assert_ne!(down, (6, 5));
+        assert!(down.0 < 7 && down.1 < 11);
+        assert_eq!(upper.tiles[upper.xy_idx(6, 5)], Some(TileType::UpStairs));
+    }
+
+    #[test]
+    fn stairs_go_on_floor_of_both_levels() {
+        let (mut upper, mut lower) = (open_map(20, 12), open_map(12, 8));
+        upper.start = Some(upper.xy_idx(2, 2));
+        upper.exit = Some(upper.xy_idx(18, 10));
+        // The lower level only has floor in its left half
+        for idx in 0..lower.tiles.len() {
+            if lower.idx_xy(idx).0 >= 5 {
+                lower.tiles[idx] = Some(TileType::Wall);
+            }
+        }
+        let walls = lower.tiles.clone();
+        connect_levels(&mut upper, &mut lower);
+
+        let (_, (x, y)) = stairs(&upper, &lower);
+        assert_eq!(walls[lower.xy_idx(x, y)], Some(TileType::Floor));
+        assert_eq!((x, y), (4, 6));
+        assert_eq!(
+            lower
+                .tiles
+                .iter()
+                .filter(|tile| **tile == Some(TileType::Floor))
+                .count(),
+            walls
+                .iter()
+                .filter(|tile| **tile == Some(TileType::Floor))
+                .count()
+                - 1
+        );
+    }
+
+    #[test]
+    fn levels_of_different_sizes_are_connected() {
+        let request = |algorithm, width, height| GenerationRequest {
+            algorithm,
+            width,
+            height,
+            options: algorithm.options(),
+            repair: RegionRepair::Join,
+            seed: 7,
+            candidates: 1,
+            fitness: Fitness::default(),
+            constraints: vec![],
+            max_attempts: 1,
+            snapshots: SnapshotPolicy::Off,
+        };
+        let dungeon = Dungeon::generate(&[
+            request(Algorithm::Bsp, 60, 40),
+            request(Algorithm::CellularAutomata, 30, 20),
+            request(Algorithm::Rooms, 50, 50),
+        ])
+        .unwrap();
+
+        let path = dungeon.save(&std::env::temp_dir()).unwrap();
+        assert!(path.ends_with(format!(
+            "dungeon-{}.json",
+            dungeon.levels[0].generation.seed
+        )));
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json, dungeon.to_json());
+        fs::remove_file(path).unwrap();
+
+        for pair in dungeon.levels.windows(2) {
+            let upper = pair[0].generation.builder.get_map();
+            let lower = pair[1].generation.builder.get_map();
+            let (down, up) = stairs(&upper, &lower);
+            assert_eq!(down, up);
+            assert_ne!(upper.start, upper.exit);
+            assert_eq!(regions(&lower, true).len(), 1);
+        }
+    }
+}
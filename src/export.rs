@@ -0,0 +1,226 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    imageops, Delay, Frame, ImageResult, Rgba, RgbaImage,
+};
+
+use crate::{map::Map, map_builders::Snapshot};
+
+/// Sprite sheet the app draws maps with.
+const ATLAS: &[u8] = include_bytes!("../assets/texture_map.png");
+/// Size of a sprite of the atlas in pixels, sprites are separated by `SPRITE_PADDING` pixels.
+const SPRITE_SIZE: u32 = 9;
+const SPRITE_PADDING: u32 = 2;
+const ATLAS_COLUMNS: u32 = 16;
+
+/// File format of an exported animation.
+#[derive(PartialEq, Eq, Copy, Clone)]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+}
+
+impl AnimationFormat {
+    /// Format matching the extension of a path, `.gif` or `.png`.
+    pub fn from_path(path: &Path) -> Result<Self, String> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gif") => Ok(AnimationFormat::Gif),
+            Some("png") | Some("apng") => Ok(AnimationFormat::Apng),
+            _ => Err(format!(
+                "Unknown animation format for {}, use .gif or .png",
+                path.display()
+            )),
+        }
+    }
+}
+
+/// Sprites of the app, used to draw maps without a window.
+pub struct Atlas(RgbaImage);
+
+impl Default for Atlas {
+    fn default() -> Self {
+        let image = image::load_from_memory(ATLAS).expect("The atlas is a valid PNG");
+        Self(image.to_rgba8())
+    }
+}
+
+impl Atlas {
+    /// Draws every tile with the sprite the app uses, the first row of the map is at the bottom
+    /// of the image as in the app.
+    pub fn render(&self, map: &Map) -> RgbaImage {
+        let mut image = RgbaImage::from_pixel(
+            map.width as u32 * SPRITE_SIZE,
+            map.height as u32 * SPRITE_SIZE,
+            Rgba([0, 0, 0, 255]),
+        );
+        for idx in 0..map.tiles.len() {
+            if let Some(sprite) = map.sprite(idx) {
+                let (x, y) = map.idx_xy(idx);
+                let sprite = self.sprite(sprite as u32);
+                let top = (map.height - 1 - y) as i64;
+                imageops::overlay(
+                    &mut image,
+                    &sprite,
+                    x as i64 * SPRITE_SIZE as i64,
+                    top * SPRITE_SIZE as i64,
+                );
+            }
+        }
+        image
+    }
+
+    /// Draws the map scaled down to fit in a square of `size` pixels, keeping its proportions.
+    pub fn thumbnail(&self, map: &Map, size: u32) -> RgbaImage {
+        let image = self.render(map);
+        let scale = size as f32 / image.width().max(image.height()) as f32;
+        let width = ((image.width() as f32 * scale) as u32).max(1);
+        let height = ((image.height() as f32 * scale) as u32).max(1);
+        imageops::thumbnail(&image, width, height)
+    }
+
+    fn sprite(&self, idx: u32) -> RgbaImage {
+        let stride = SPRITE_SIZE + SPRITE_PADDING;
+        let x = idx % ATLAS_COLUMNS * stride;
+        let y = idx / ATLAS_COLUMNS * stride;
+        imageops::crop_imm(&self.0, x, y, SPRITE_SIZE, SPRITE_SIZE).to_image()
+    }
+}
+
+/// Writes the snapshots as an animation looping forever, every frame is shown for `delay`
+/// milliseconds.
+///
+/// The format is picked from the extension of the path.
+pub fn export_animation(snapshots: &[Snapshot], delay: u64, path: &Path) -> Result<(), String> {
+    let format = AnimationFormat::from_path(path)?;
+    if snapshots.is_empty() {
+        return Err("No snapshots to export".to_string());
+    }
+
+    let atlas = Atlas::default();
+    let frames: Vec<RgbaImage> = snapshots
+        .iter()
+        .map(|snapshot| atlas.render(&snapshot.map))
+        .collect();
+
+    let file = File::create(path)
+        .map_err(|err| format!("Could not create {}: {}", path.display(), err))?;
+    let writer = BufWriter::new(file);
+    match format {
+        AnimationFormat::Gif => write_gif(writer, frames, delay).map_err(|err| err.to_string()),
+        AnimationFormat::Apng => write_apng(writer, &frames, delay).map_err(|err| err.to_string()),
+    }
+    .map_err(|err| format!("Could not write {}: {}", path.display(), err))
+}
+
+fn write_gif<W: Write>(writer: W, frames: Vec<RgbaImage>, delay: u64) -> ImageResult<()> {
+    let delay = Delay::from_numer_denom_ms(delay.min(u32::MAX as u64) as u32, 1);
+    let mut encoder = GifEncoder::new_with_speed(writer, 10);
+    encoder.set_repeat(Repeat::Infinite)?;
+    encoder.encode_frames(
+        frames
+            .into_iter()
+            .map(|frame| Frame::from_parts(frame, 0, 0, delay)),
+    )
+}
+
+fn write_apng<W: Write>(
+    writer: W,
+    frames: &[RgbaImage],
+    delay: u64,
+) -> Result<(), png::EncodingError> {
+    let (width, height) = frames[0].dimensions();
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(frames.len() as u32, 0)?;
+    encoder.set_frame_delay(delay.min(u16::MAX as u64) as u16, 1000)?;
+
+    let mut writer = encoder.write_header()?;
+    for frame in frames {
+        writer.write_image_data(frame.as_raw())?;
+    }
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::BufReader};
+
+    use image::{codecs::gif::GifDecoder, AnimationDecoder};
+
+    use super::*;
+    use crate::map::TileType;
+
+    /// Snapshots of a small map digging one more floor tile at every step.
+    fn digging(steps: usize) -> Vec<Snapshot> {
+        let mut map = Map::new(5, 3);
+        map.tiles.fill(Some(TileType::Wall));
+        (0..steps)
+            .map(|step| {
+                map.tiles[step] = Some(TileType::Floor);
+                Snapshot {
+                    map: map.clone(),
+                    ..Default::default()
+                }
+            })
+            .collect()
+    }
+
+    fn exported(name: &str, snapshots: &[Snapshot]) -> (Vec<u8>, File) {
+        let path = std::env::temp_dir().join(name);
+        export_animation(snapshots, 50, &path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        let file = File::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        (bytes, file)
+    }
+
+    #[test]
+    fn gif_frames_follow_the_snapshots() {
+        let snapshots = digging(4);
+        let (bytes, file) = exported("export-test.gif", &snapshots);
+        assert!(bytes.starts_with(b"GIF89a"));
+
+        let decoder = GifDecoder::new(BufReader::new(file)).unwrap();
+        let frames = decoder.into_frames().collect_frames().unwrap();
+        assert_eq!(frames.len(), snapshots.len());
+        let atlas = Atlas::default();
+        for (frame, snapshot) in frames.iter().zip(&snapshots) {
+            assert!(*frame.buffer() == atlas.render(&snapshot.map));
+            assert_eq!(frame.delay(), Delay::from_numer_denom_ms(50, 1));
+        }
+    }
+
+    #[test]
+    fn apng_frames_follow_the_snapshots() {
+        let snapshots = digging(3);
+        let (bytes, file) = exported("export-test.png", &snapshots);
+        assert!(bytes.starts_with(b"\x89PNG"));
+
+        let mut reader = png::Decoder::new(BufReader::new(file)).read_info().unwrap();
+        let control = reader.info().animation_control.unwrap();
+        assert_eq!(control.num_frames, snapshots.len() as u32);
+        assert_eq!(control.num_plays, 0);
+        let atlas = Atlas::default();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        for snapshot in &snapshots {
+            reader.next_frame(&mut buffer).unwrap();
+            assert_eq!(buffer, atlas.render(&snapshot.map).into_raw());
+        }
+    }
+
+    #[test]
+    fn exports_refuse_unknown_formats_and_empty_histories() {
+        let path = std::env::temp_dir().join("export-test.bmp");
+        assert!(export_animation(&digging(1), 50, &path).is_err());
+        assert_eq!(
+            export_animation(&[], 50, &path.with_extension("gif")),
+            Err("No snapshots to export".to_string())
+        );
+    }
+}
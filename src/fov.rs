@@ -0,0 +1,121 @@
+use std::collections::HashSet;
+
+use crate::map::Map;
+
+/// Turns a (depth, column) pair of a quadrant into an offset from the origin.
+type Quadrant = fn(i32, i32) -> (i32, i32);
+
+/// Quadrants facing up, down, right and left.
+const QUADRANTS: [Quadrant; 4] = [
+    |depth, col| (col, depth),
+    |depth, col| (col, -depth),
+    |depth, col| (depth, col),
+    |depth, col| (-depth, col),
+];
+
+/// Slope of a line from the origin, kept as a fraction so every comparison is exact.
+#[derive(Clone, Copy)]
+struct Slope {
+    num: i32,
+    den: i32,
+}
+
+impl Slope {
+    /// Slope of the left edge of a tile.
+    fn of_tile(depth: i32, col: i32) -> Self {
+        Self {
+            num: 2 * col - 1,
+            den: 2 * depth,
+        }
+    }
+}
+
+/// Tiles of a quadrant at the same distance from the origin, between two slopes.
+struct Row {
+    depth: i32,
+    start: Slope,
+    end: Slope,
+}
+
+impl Row {
+    /// First column, `depth * start` rounded half up.
+    fn min_col(&self) -> i32 {
+        (2 * self.depth * self.start.num + self.start.den).div_euclid(2 * self.start.den)
+    }
+
+    /// Last column, `depth * end` rounded half down.
+    fn max_col(&self) -> i32 {
+        -(self.end.den - 2 * self.depth * self.end.num).div_euclid(2 * self.end.den)
+    }
+
+    /// Whether the center of the tile is inside the row, floor tiles are only visible then so
+    /// that sight is symmetric.
+    fn is_symmetric(&self, col: i32) -> bool {
+        col * self.start.den >= self.depth * self.start.num
+            && col * self.end.den <= self.depth * self.end.num
+    }
+}
+
+/// Tiles visible from `origin` within `radius` tiles, the origin included.
+///
+/// Walls and closed doors block the sight but are visible themselves. The field of view is
+/// symmetric: a floor tile sees another one if and only if that one sees it back.
+pub fn field_of_view(map: &Map, origin: usize, radius: i32) -> HashSet<usize> {
+    let (ox, oy) = map.idx_xy(origin);
+    let tile = |quadrant: Quadrant, depth: i32, col: i32| {
+        let (dx, dy) = quadrant(depth, col);
+        let (x, y) = (ox + dx, oy + dy);
+        let inside = x >= 0 && x < map.width && y >= 0 && y < map.height;
+        let visible = inside && dx * dx + dy * dy <= radius * radius;
+        (inside.then(|| map.xy_idx(x, y)), visible)
+    };
+
+    let mut visible = HashSet::from([origin]);
+    for quadrant in QUADRANTS {
+        let mut rows = vec![Row {
+            depth: 1,
+            start: Slope { num: -1, den: 1 },
+            end: Slope { num: 1, den: 1 },
+        }];
+        while let Some(mut row) = rows.pop() {
+            if row.depth > radius {
+                continue;
+            }
+
+            let mut previous = None;
+            for col in row.min_col()..=row.max_col() {
+                let (idx, in_radius) = tile(quadrant, row.depth, col);
+                // Tiles outside the map block the sight like walls
+                let blocks = idx.is_none_or(|idx| map.is_opaque(idx));
+                if in_radius && (blocks || row.is_symmetric(col)) {
+                    visible.extend(idx);
+                }
+
+                match (previous, blocks) {
+                    (Some(true), false) => row.start = Slope::of_tile(row.depth, col),
+                    (Some(false), true) => rows.push(Row {
+                        depth: row.depth + 1,
+                        start: row.start,
+                        end: Slope::of_tile(row.depth, col),
+                    }),
+                    _ => {}
+                }
+                previous = Some(blocks);
+            }
+            if previous == Some(false) {
+                rows.push(Row {
+                    depth: row.depth + 1,
+                    ..row
+                });
+            }
+        }
+    }
+    visible
+}
+
+/// Whether `to` is in the field of view of `from`, so it's symmetric too.
+pub fn line_of_sight(map: &Map, from: usize, to: usize) -> bool {
+    let ((x1, y1), (x2, y2)) = (map.idx_xy(from), map.idx_xy(to));
+    let radius = (((x2 - x1).pow(2) + (y2 - y1).pow(2)) as f32).sqrt().ceil() as i32;
+    field_of_view(map, from, radius).contains(&to)
+}
@@ -0,0 +1,409 @@
+use std::{fmt, ops::Range};
+
+use crate::{
+    algorithms::{Algorithm, Option},
+    map_builders::{
+        split_areas, GenerationError, MapBuilder, RegionRepair, SnapshotPolicy, AREA_SIZE,
+    },
+    metrics::MapMetrics,
+};
+
+/// Scores a finished map, the candidate with the highest score is kept.
+///
+/// Every score is between `0` and `1`, `1` being a perfect match.
+#[derive(PartialEq, Eq, Copy, Clone)]
+pub enum Fitness {
+    /// Floor ratio closest to a percentage.
+    FloorRatio(i32),
+    /// Start to exit path of at least this many steps.
+    PathLength(i32),
+    /// Room count closest to this number.
+    RoomCount(i32),
+}
+
+impl Default for Fitness {
+    fn default() -> Self {
+        Fitness::FloorRatio(45)
+    }
+}
+
+impl fmt::Display for Fitness {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fitness::FloorRatio(_) => write!(f, "Floor ratio"),
+            Fitness::PathLength(_) => write!(f, "Minimum path length"),
+            Fitness::RoomCount(_) => write!(f, "Room count"),
+        }
+    }
+}
+
+impl Fitness {
+    pub fn all() -> Vec<Self> {
+        vec![
+            Fitness::default(),
+            Fitness::PathLength(60),
+            Fitness::RoomCount(8),
+        ]
+    }
+
+    pub fn value_mut(&mut self) -> &mut i32 {
+        match self {
+            Fitness::FloorRatio(value) | Fitness::PathLength(value) | Fitness::RoomCount(value) => {
+                value
+            }
+        }
+    }
+
+    pub fn range(&self) -> (i32, i32) {
+        match self {
+            Fitness::FloorRatio(_) => (1, 100),
+            Fitness::PathLength(_) => (1, 500),
+            Fitness::RoomCount(_) => (1, 50),
+        }
+    }
+
+    pub fn score(&self, metrics: &MapMetrics) -> f32 {
+        match *self {
+            Fitness::FloorRatio(target) => 1. - (metrics.floor_ratio - target as f32 / 100.).abs(),
+            Fitness::PathLength(min) => {
+                let length = metrics.path_length.unwrap_or(0) as f32;
+                (length / min.max(1) as f32).min(1.)
+            }
+            Fitness::RoomCount(target) => {
+                let target = target.max(1) as f32;
+                (1. - (metrics.rooms as f32 - target).abs() / target).max(0.)
+            }
+        }
+    }
+}
+
+/// Requirement a generated map must meet to be kept.
+#[derive(PartialEq, Eq, Copy, Clone)]
+pub enum Constraint {
+    /// Start to exit path of at least this many steps.
+    MinPathLength(i32),
+    MinRooms(i32),
+    /// Every floor tile can be reached from any other.
+    NoIsolatedRegion,
+    /// Floor ratio between two percentages.
+    FloorRatio(i32, i32),
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Constraint::MinPathLength(min) => write!(f, "Path of at least {} tiles", min),
+            Constraint::MinRooms(min) => write!(f, "At least {} rooms", min),
+            Constraint::NoIsolatedRegion => write!(f, "No isolated region"),
+            Constraint::FloorRatio(min, max) => {
+                write!(f, "Floor ratio between {}% and {}%", min, max)
+            }
+        }
+    }
+}
+
+impl Constraint {
+    pub fn all() -> Vec<Self> {
+        vec![
+            Constraint::MinPathLength(60),
+            Constraint::MinRooms(8),
+            Constraint::NoIsolatedRegion,
+            Constraint::FloorRatio(35, 55),
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Constraint::MinPathLength(_) => "Minimum path length",
+            Constraint::MinRooms(_) => "Minimum rooms",
+            Constraint::NoIsolatedRegion => "No isolated region",
+            Constraint::FloorRatio(_, _) => "Floor ratio",
+        }
+    }
+
+    /// Values of the constraint with their allowed range.
+    pub fn values_mut(&mut self) -> Vec<(&mut i32, i32, i32)> {
+        match self {
+            Constraint::MinPathLength(min) => vec![(min, 1, 500)],
+            Constraint::MinRooms(min) => vec![(min, 1, 50)],
+            Constraint::NoIsolatedRegion => vec![],
+            Constraint::FloorRatio(min, max) => vec![(min, 0, 100), (max, 0, 100)],
+        }
+    }
+
+    pub fn check(&self, metrics: &MapMetrics) -> bool {
+        match *self {
+            Constraint::MinPathLength(min) => metrics.path_length.unwrap_or(0) >= min as usize,
+            Constraint::MinRooms(min) => metrics.rooms >= min as usize,
+            Constraint::NoIsolatedRegion => metrics.regions == 1,
+            Constraint::FloorRatio(min, max) => {
+                let ratio = metrics.floor_ratio * 100.;
+                ratio >= min as f32 && ratio <= max as f32
+            }
+        }
+    }
+}
+
+/// Everything needed to generate a map.
+#[derive(Clone)]
+pub struct GenerationRequest {
+    pub algorithm: Algorithm,
+    pub width: i32,
+    pub height: i32,
+    pub options: Vec<Option>,
+    pub repair: RegionRepair,
+    /// Seed of the first candidate, the others use the following seeds.
+    pub seed: u64,
+    /// Maps generated on every attempt, only the best one is kept.
+    pub candidates: usize,
+    pub fitness: Fitness,
+    pub constraints: Vec<Constraint>,
+    /// Attempts made until a candidate meets every constraint.
+    pub max_attempts: usize,
+    /// Snapshots recorded by every candidate.
+    pub snapshots: SnapshotPolicy,
+}
+
+pub struct Generation {
+    pub builder: Box<dyn MapBuilder>,
+    pub seed: u64,
+    pub score: f32,
+    /// Score of every candidate, the winner included.
+    pub scores: Vec<f32>,
+    /// Whether the kept map meets every constraint.
+    pub satisfied: bool,
+    pub attempts: usize,
+    /// Number of candidates failing each constraint.
+    pub failures: Vec<(Constraint, usize)>,
+}
+
+impl Generation {
+    /// Returns the constraint that failed the most, if any failed.
+    pub fn most_failed(&self) -> std::option::Option<(Constraint, usize)> {
+        self.failures
+            .iter()
+            .copied()
+            .filter(|(_, count)| *count > 0)
+            .max_by_key(|(_, count)| *count)
+    }
+}
+
+struct Candidate {
+    builder: Box<dyn MapBuilder>,
+    seed: u64,
+    score: f32,
+    satisfied: bool,
+}
+
+impl Candidate {
+    /// Candidates meeting the constraints always win, the fitness decides between the rest.
+    fn beats(&self, other: &std::option::Option<Candidate>) -> bool {
+        match other {
+            Some(other) => (self.satisfied, self.score) > (other.satisfied, other.score),
+            None => true,
+        }
+    }
+}
+
+/// Candidates generated on a single thread.
+struct Batch {
+    best: std::option::Option<Candidate>,
+    scores: Vec<f32>,
+    failures: Vec<usize>,
+    /// Why the last candidate that couldn't be built failed.
+    error: std::option::Option<GenerationError>,
+}
+
+impl Batch {
+    fn merge(&mut self, other: Batch) {
+        self.scores.extend(other.scores);
+        for (count, other) in self.failures.iter_mut().zip(other.failures) {
+            *count += other;
+        }
+        if let Some(candidate) = other.best {
+            if candidate.beats(&self.best) {
+                self.best = Some(candidate);
+            }
+        }
+        if other.error.is_some() {
+            self.error = other.error;
+        }
+    }
+}
+
+/// Generates candidates until one meets every constraint or the attempts run out, then returns
+/// the best one.
+///
+/// Candidates that can't be built are skipped, an error is only returned if none could be.
+pub fn generate(request: &GenerationRequest) -> Result<Generation, GenerationError> {
+    if request.algorithm == Algorithm::None {
+        return Err(GenerationError::NoAlgorithm);
+    }
+    let errors = request.algorithm.validate(&request.options);
+    if !errors.is_empty() {
+        return Err(GenerationError::InvalidSettings(errors));
+    }
+
+    let count = request.candidates.max(1);
+    let mut result = Batch {
+        best: None,
+        scores: vec![],
+        failures: vec![0; request.constraints.len()],
+        error: None,
+    };
+
+    let mut attempts = 0;
+    while attempts < request.max_attempts.max(1) {
+        let seed = request.seed.wrapping_add((attempts * count) as u64);
+        for batch in generate_attempt(request, seed, count) {
+            result.merge(batch);
+        }
+        attempts += 1;
+
+        if result.best.as_ref().is_some_and(|best| best.satisfied) {
+            break;
+        }
+    }
+
+    let best = match result.best {
+        Some(best) => best,
+        None => {
+            return Err(result
+                .error
+                .expect("Candidates without a map have an error"))
+        }
+    };
+    Ok(Generation {
+        builder: best.builder,
+        seed: best.seed,
+        score: best.score,
+        scores: result.scores,
+        satisfied: best.satisfied,
+        attempts,
+        failures: request
+            .constraints
+            .iter()
+            .copied()
+            .zip(result.failures)
+            .collect(),
+    })
+}
+
+/// Generates `count` candidates with consecutive seeds.
+fn generate_attempt(request: &GenerationRequest, seed: u64, count: usize) -> Vec<Batch> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let threads = std::thread::available_parallelism()
+            .map_or(1, |n| n.get())
+            .min(count);
+        std::thread::scope(|s| {
+            let mut first = seed;
+            let handles: Vec<_> = (0..threads)
+                .map(|t| {
+                    let n = (count / threads + usize::from(t < count % threads)) as u64;
+                    let seeds = first..first + n;
+                    first += n;
+                    s.spawn(move || generate_batch(request, seeds))
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("Candidate generation panicked"))
+                .collect()
+        })
+    }
+
+    // Threads aren't available in the browser
+    #[cfg(target_arch = "wasm32")]
+    vec![generate_batch(request, seed..seed + count as u64)]
+}
+
+/// Generates a candidate for every seed, one after the other, keeping only the best builder.
+fn generate_batch(request: &GenerationRequest, seeds: Range<u64>) -> Batch {
+    let mut batch = Batch {
+        best: None,
+        scores: vec![],
+        failures: vec![0; request.constraints.len()],
+        error: None,
+    };
+
+    for seed in seeds {
+        let mut builder = match request.algorithm.get(&request.options) {
+            Ok(builder) => builder,
+            Err(err) => {
+                batch.error = Some(err);
+                continue;
+            }
+        };
+        builder.set_snapshot_policy(request.snapshots);
+        builder.set_region_repair(request.repair);
+        if let Err(err) = builder.build_map(request.width, request.height, seed) {
+            batch.error = Some(err);
+            continue;
+        }
+        // Caves have no rooms to spread spawns over
+        if builder.get_rooms().is_none() {
+            split_areas(builder.as_mut(), AREA_SIZE, seed);
+        }
+
+        let metrics = MapMetrics::new(&builder.get_map(), builder.get_rooms().as_deref());
+        let score = request.fitness.score(&metrics);
+
+        let mut satisfied = true;
+        for (constraint, failures) in request.constraints.iter().zip(batch.failures.iter_mut()) {
+            if !constraint.check(&metrics) {
+                *failures += 1;
+                satisfied = false;
+            }
+        }
+
+        batch.scores.push(score);
+        let candidate = Candidate {
+            builder,
+            seed,
+            score,
+            satisfied,
+        };
+        if candidate.beats(&batch.best) {
+            batch.best = Some(candidate);
+        }
+    }
+
+    batch
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_builders::{RegionRepair, SnapshotPolicy};
+
+    #[test]
+    fn the_kept_seed_builds_the_kept_map_again() {
+        let request = GenerationRequest {
+            algorithm: Algorithm::CellularAutomata,
+            width: 40,
+            height: 30,
+            options: Algorithm::CellularAutomata.options(),
+            repair: RegionRepair::Join,
+            seed: 3,
+            candidates: 6,
+            fitness: Fitness::PathLength(60),
+            constraints: vec![Constraint::MinPathLength(1000)],
+            max_attempts: 3,
+            snapshots: SnapshotPolicy::Off,
+        };
+        let generation = generate(&request).unwrap();
+        let again = generate(&GenerationRequest {
+            seed: generation.seed,
+            candidates: 1,
+            max_attempts: 1,
+            ..request.clone()
+        })
+        .unwrap();
+        assert_eq!(again.seed, generation.seed);
+        assert_eq!(
+            again.builder.get_map().rows(),
+            generation.builder.get_map().rows()
+        );
+    }
+}
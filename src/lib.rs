@@ -0,0 +1,13 @@
+pub mod algorithms;
+pub mod analysis;
+pub mod dungeon;
+pub mod export;
+pub mod fov;
+pub mod generator;
+pub mod map;
+pub mod map_builders;
+pub mod metrics;
+pub mod preset;
+pub mod puzzle;
+pub mod room_graph;
+pub mod spawner;
@@ -2,38 +2,91 @@ use bevy::prelude::*;
 use bevy_egui::EguiPlugin;
 use debug::DebugPlugin;
 use iyes_loopless::prelude::*;
-use map::Map;
-use std::collections::VecDeque;
-use ui::{draw_ui, Config};
+use overlay::{
+    draw_analysis, draw_fov, draw_overlay, pick_fov_origin, FovPreview, LastSnapshot, MapAnalysis,
+    OverlaySettings,
+};
+use play::{draw_player, enter_play, leave_play, move_player};
+use procedural_map::{
+    analysis::Analysis,
+    dungeon::Dungeon,
+    generator::Generation,
+    map::{Map, TextureMap, TILE_SIZE},
+    map_builders::{MapBuilder, Overlay, Snapshot},
+    metrics::MapMetrics,
+};
+use ui::{draw_comparison, draw_gallery, draw_play, draw_timeline, draw_ui, Config};
 
-mod algorithms;
+mod cli;
 mod debug;
-mod map;
-mod map_builders;
+mod overlay;
+mod play;
+#[cfg(target_arch = "wasm32")]
+mod share;
 mod ui;
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 enum States {
     Menu,
     Running,
+    /// Playing the snapshots of the shown level again from a chosen frame.
+    Replaying,
+    /// Walking the shown level with the player.
+    Play,
 }
 
 #[derive(Component)]
 struct MapComponent;
 
-struct Snapshots(VecDeque<Map>);
+/// Parent of the compared map, drawn right of the shown level.
+#[derive(Component)]
+struct ComparisonMap;
+
+/// Snapshots of the shown level, drawn one after the other.
+struct Timeline {
+    frames: Vec<Snapshot>,
+    /// Frame drawn on the next tick.
+    next: usize,
+}
+
+impl Timeline {
+    /// Frame drawn last, if any.
+    fn current(&self) -> Option<&Snapshot> {
+        self.next
+            .checked_sub(1)
+            .and_then(|idx| self.frames.get(idx))
+    }
+}
+
 struct CurrentMap(Vec<Entity>);
-pub struct TextureMap(Handle<TextureAtlas>);
+struct CurrentDungeon(Dungeon);
+/// Map generated with the comparison settings.
+struct CurrentComparison(Generation);
+
+/// Compared map being played next to the shown level.
+struct ComparisonView {
+    timeline: Timeline,
+    tiles: Vec<Entity>,
+    metrics: MapMetrics,
+    seed: u64,
+}
 
 fn main() {
-    App::new()
-        .insert_resource(ClearColor(Color::rgb(0., 0., 0.)))
+    // Any argument means the map is generated from the command line
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(cli::run(&args));
+    }
+
+    let mut app = App::new();
+    app.insert_resource(ClearColor(Color::rgb(0., 0., 0.)))
         .insert_resource(WindowDescriptor {
             title: "Procedural dungeon demo".to_string(),
             fit_canvas_to_parent: true,
             ..default()
         })
         .init_resource::<Config>()
+        .init_resource::<OverlaySettings>()
         .add_plugins(DefaultPlugins)
         .add_plugin(EguiPlugin)
         .add_plugin(DebugPlugin)
@@ -41,12 +94,36 @@ fn main() {
         .add_startup_system(spawn_camera)
         .add_startup_system(load_assets)
         .add_system(draw_ui)
-        .add_system(draw_map.run_in_state(States::Running))
+        .add_system(draw_timeline)
+        .add_system(draw_comparison)
+        .add_system(draw_gallery)
+        .add_system(draw_play)
+        .add_system(
+            draw_map
+                .run_not_in_state(States::Menu)
+                .run_not_in_state(States::Play),
+        )
+        .add_system(draw_overlay)
+        .add_system(draw_analysis)
+        .add_system(pick_fov_origin.run_in_state(States::Menu))
+        .add_system(draw_fov)
+        .add_system(move_player.run_in_state(States::Play))
+        .add_system(draw_player.run_in_state(States::Play))
+        .add_enter_system(States::Play, enter_play)
+        .add_exit_system(States::Play, leave_play)
         .add_enter_system(
             States::Running,
             despawn_with::<MapComponent>.chain(setup_map),
-        )
-        .run();
+        );
+
+    // There's no file system to read presets from in the browser
+    #[cfg(not(target_arch = "wasm32"))]
+    app.add_startup_system(ui::load_presets);
+
+    #[cfg(target_arch = "wasm32")]
+    app.add_startup_system(ui::load_link);
+
+    app.run();
 }
 
 fn despawn_with<T: Component>(mut commands: Commands, q: Query<Entity, With<T>>) {
@@ -78,39 +155,113 @@ fn spawn_camera(mut cmd: Commands) {
     cmd.spawn_bundle(Camera2dBundle::default());
 }
 
-fn setup_map(cfg: Res<Config>, mut cmd: Commands) {
-    // Spawn parent map component for easy positioning
-    let parent = cmd
-        .spawn_bundle(SpatialBundle {
-            transform: Transform::from_xyz(cfg.offset / 2., 0., 0.),
-            ..default()
-        })
-        .insert(MapComponent)
-        .id();
+fn setup_map(
+    cfg: Res<Config>,
+    dungeon: Res<CurrentDungeon>,
+    comparison: Option<Res<CurrentComparison>>,
+    windows: Res<Windows>,
+    mut cmd: Commands,
+) {
+    // Show the selected level of the generated dungeon
+    let generation = &dungeon.0.levels[cfg.level.min(dungeon.0.len() - 1)].generation;
+    let builder = &generation.builder;
+    let map = builder.get_map();
 
-    // Generate map with chosen algorithm
-    let mut builder = cfg.algorithm.get();
-    builder.build_map(cfg.width, cfg.height, &cfg.options);
+    // Both maps are scaled down together when they don't fit side by side
+    let (position, scale) = match &comparison {
+        Some(comparison) => {
+            let compared = comparison.0.builder.get_map();
+            let width = windows.get_primary().map_or(0., |window| window.width());
+            let gap = 2. * TILE_SIZE as f32;
+            let left = (map.width * TILE_SIZE) as f32;
+            let right = (compared.width * TILE_SIZE) as f32;
+            let total = left + right + gap;
+            let scale = ((width - cfg.offset) / total).clamp(0.1, 1.);
+            let center = |x: f32| Vec3::new(cfg.offset / 2. + x * scale, 0., 0.);
+
+            let parent = spawn_parent(&mut cmd, center(total / 2. - right / 2.), scale);
+            cmd.entity(parent).insert(ComparisonMap);
+            let tiles = spawn_tiles(&mut cmd, parent, &compared);
+            cmd.insert_resource(ComparisonView {
+                timeline: Timeline {
+                    frames: frames(comparison.0.builder.as_ref()),
+                    next: 0,
+                },
+                tiles,
+                metrics: MapMetrics::new(&compared, comparison.0.builder.get_rooms().as_deref()),
+                seed: comparison.0.seed,
+            });
+            (center(-total / 2. + left / 2.), scale)
+        }
+        None => {
+            cmd.remove_resource::<ComparisonView>();
+            (Vec3::new(cfg.offset / 2., 0., 0.), 1.)
+        }
+    };
+
+    // Spawn parent map component for easy positioning
+    let parent = spawn_parent(&mut cmd, position, scale);
 
     // Store snapshots for later use
-    let snaphots = builder.get_snapshot_history();
-    println!("Snapshots: {}", snaphots.len());
-    cmd.insert_resource(Snapshots(snaphots));
+    let frames = frames(builder.as_ref());
+    println!("Snapshots: {}", frames.len());
+    cmd.insert_resource(Timeline { frames, next: 0 });
+    cmd.remove_resource::<LastSnapshot>();
+    cmd.remove_resource::<FovPreview>();
 
-    // Spawn tile entities
-    let mut entities = vec![Entity::from_raw(0); builder.get_map().tiles.len()];
-    let map = builder.get_map();
-    for (idx, _) in map.tiles.iter().enumerate() {
-        let entity = cmd.spawn().id();
-        cmd.entity(parent).add_child(entity);
-        entities[idx] = entity;
+    let tiles = spawn_tiles(&mut cmd, parent, &map);
+    cmd.insert_resource(CurrentMap(tiles));
+    cmd.insert_resource(MapAnalysis {
+        analysis: Analysis::new(&map),
+        metrics: MapMetrics::new(&map, builder.get_rooms().as_deref()),
+        map,
+        most_failed: generation.most_failed(),
+        score: generation.score,
+        scores: generation.scores.clone(),
+        seed: generation.seed,
+        satisfied: generation.satisfied,
+        attempts: generation.attempts,
+        failures: generation.failures.clone(),
+    });
+}
+
+fn spawn_parent(cmd: &mut Commands, position: Vec3, scale: f32) -> Entity {
+    cmd.spawn_bundle(SpatialBundle {
+        transform: Transform::from_translation(position).with_scale(Vec3::splat(scale)),
+        ..default()
+    })
+    .insert(MapComponent)
+    .id()
+}
+
+/// Spawns an empty entity for every tile of the map.
+fn spawn_tiles(cmd: &mut Commands, parent: Entity, map: &Map) -> Vec<Entity> {
+    (0..map.tiles.len())
+        .map(|_| {
+            let entity = cmd.spawn().id();
+            cmd.entity(parent).add_child(entity);
+            entity
+        })
+        .collect()
+}
+
+/// Snapshots of a builder, the final map is shown when none were recorded.
+fn frames(builder: &dyn MapBuilder) -> Vec<Snapshot> {
+    let mut frames = Vec::from(builder.get_snapshot_history());
+    if frames.is_empty() {
+        frames.push(Snapshot {
+            phase: "final map".to_string(),
+            label: "final map".to_string(),
+            ..Snapshot::new(&builder.get_map(), &Overlay::default())
+        });
     }
-    cmd.insert_resource(CurrentMap(entities));
+    frames
 }
 
 fn draw_map(
     mut cmd: Commands,
-    mut snaps: ResMut<Snapshots>,
+    mut timeline: ResMut<Timeline>,
+    comparison: Option<ResMut<ComparisonView>>,
     current_map: Res<CurrentMap>,
     texture: Res<TextureMap>,
     mut cfg: ResMut<Config>,
@@ -119,9 +270,24 @@ fn draw_map(
     // tick the timer
     cfg.speed_timer.tick(time.delta());
     if cfg.speed_timer.finished() {
-        if let Some(snap) = snaps.0.pop_front() {
-            snap.draw(cmd, texture, &current_map.0)
-        } else {
+        let mut drawn = false;
+        if let Some(snap) = timeline.frames.get(timeline.next) {
+            snap.map.draw(&mut cmd, &texture, &current_map.0);
+            cmd.insert_resource(LastSnapshot(snap.clone()));
+            timeline.next += 1;
+            drawn = true;
+        }
+        // The compared map plays in sync, each map stays on its last frame once done
+        if let Some(mut comparison) = comparison {
+            let comparison = &mut *comparison;
+            let next = comparison.timeline.next;
+            if let Some(snap) = comparison.timeline.frames.get(next) {
+                snap.map.draw(&mut cmd, &texture, &comparison.tiles);
+                comparison.timeline.next += 1;
+                drawn = true;
+            }
+        }
+        if !drawn {
             println!("No more snapshots");
             cmd.insert_resource(NextState(States::Menu));
         }
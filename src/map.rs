@@ -1,16 +1,52 @@
 use bevy::prelude::*;
 use bracket_pathfinding::prelude::{BaseMap, DistanceAlg, Point, SmallVec};
 
-use crate::TextureMap;
+use crate::{
+    puzzle::{Lock, KEY_GLYPH},
+    spawner::Spawn,
+};
 
-const TILE_SIZE: i32 = 16;
+pub const TILE_SIZE: i32 = 16;
 
-#[derive(Default, Clone, PartialEq, Eq)]
+pub struct TextureMap(pub Handle<TextureAtlas>);
+
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
 pub enum TileType {
     #[default]
     Floor,
     Wall,
     Proggress,
+    UpStairs,
+    DownStairs,
+    Door {
+        open: bool,
+    },
+}
+
+impl TileType {
+    /// Whether the player can stand on this tile, closed doors are opened by walking through.
+    pub fn is_walkable(&self) -> bool {
+        matches!(
+            self,
+            TileType::Floor | TileType::UpStairs | TileType::DownStairs | TileType::Door { .. }
+        )
+    }
+
+    /// Whether this tile blocks the sight.
+    pub fn is_opaque(&self) -> bool {
+        matches!(self, TileType::Wall | TileType::Door { open: false })
+    }
+
+    pub fn glyph(&self) -> char {
+        match self {
+            TileType::Floor | TileType::Proggress => '.',
+            TileType::Wall => '#',
+            TileType::UpStairs => '<',
+            TileType::DownStairs => '>',
+            TileType::Door { open: false } => '+',
+            TileType::Door { open: true } => '/',
+        }
+    }
 }
 
 #[derive(Default, Clone)]
@@ -18,6 +54,14 @@ pub struct Map {
     pub tiles: Vec<Option<TileType>>,
     pub width: i32,
     pub height: i32,
+    pub start: Option<usize>,
+    pub exit: Option<usize>,
+    /// Area of every walkable tile for maps without rooms, empty until the map is split.
+    pub areas: Vec<Option<usize>>,
+    /// Monsters, items and traps placed once the map is finished.
+    pub spawns: Vec<Spawn>,
+    /// Locked doors and their keys, in the order they are placed from the start.
+    pub locks: Vec<Lock>,
 }
 
 impl Map {
@@ -26,36 +70,72 @@ impl Map {
             width,
             height,
             tiles: vec![None; (width * height) as usize],
+            ..default()
         }
     }
 
-    pub fn draw(&self, mut cmds: Commands, texture: Res<TextureMap>, tiles: &[Entity]) {
-        for (idx, tile) in self.tiles.iter().enumerate() {
-            if let Some(tile) = tile {
+    pub fn draw(&self, cmds: &mut Commands, texture: &TextureMap, tiles: &[Entity]) {
+        for (idx, entity) in tiles.iter().enumerate() {
+            if let Some(index) = self.sprite(idx) {
                 let (x, y) = self.idx_xy(idx);
 
-                let mut sprite = match *tile {
-                    TileType::Floor => TextureAtlasSprite::new(255),
-                    TileType::Wall => TextureAtlasSprite::new(self.wall_texture(x, y)),
-                    TileType::Proggress => TextureAtlasSprite::new(94),
-                };
-
+                let mut sprite = TextureAtlasSprite::new(index);
                 sprite.custom_size = Some(Vec2::splat(TILE_SIZE as f32));
 
-                cmds.entity(tiles[idx]).insert_bundle(SpriteSheetBundle {
+                cmds.entity(*entity).insert_bundle(SpriteSheetBundle {
                     texture_atlas: texture.0.clone(),
-                    sprite: sprite,
-                    transform: Transform::from_xyz(
-                        (x * TILE_SIZE - (TILE_SIZE * self.width / 2)) as f32,
-                        (y * TILE_SIZE - (TILE_SIZE * self.height / 2)) as f32,
-                        0.,
-                    ),
+                    sprite,
+                    transform: Transform::from_translation(self.tile_position(x, y).extend(0.)),
                     ..default()
                 });
             }
         }
     }
 
+    /// Atlas sprite of a tile, `None` for unset tiles. Spawns and keys are drawn instead of
+    /// their tile.
+    pub fn sprite(&self, idx: usize) -> Option<usize> {
+        if let Some(spawn) = self.spawns.iter().find(|spawn| spawn.idx == idx) {
+            return Some(spawn.glyph as usize);
+        }
+        if self.locks.iter().any(|lock| lock.key == idx) {
+            return Some(KEY_GLYPH as usize);
+        }
+        let (x, y) = self.idx_xy(idx);
+        self.tiles[idx].as_ref().map(|tile| match tile {
+            TileType::Floor => 255,
+            TileType::Wall => self.wall_texture(x, y),
+            TileType::Proggress => 94,
+            TileType::UpStairs => 60,
+            TileType::DownStairs => 62,
+            TileType::Door { open: false } => 43,
+            TileType::Door { open: true } => 47,
+        })
+    }
+
+    /// Position of the center of a tile relative to the map parent entity.
+    pub fn tile_position(&self, x: i32, y: i32) -> Vec2 {
+        Vec2::new(
+            (x * TILE_SIZE - (TILE_SIZE * self.width / 2)) as f32,
+            (y * TILE_SIZE - (TILE_SIZE * self.height / 2)) as f32,
+        )
+    }
+
+    /// Glyph of every tile, one string per row from the top, unset tiles are blank.
+    pub fn rows(&self) -> Vec<String> {
+        (0..self.height)
+            .map(|y| {
+                (0..self.width)
+                    .map(|x| {
+                        self.tiles[self.xy_idx(x, y)]
+                            .as_ref()
+                            .map_or(' ', TileType::glyph)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
     pub fn idx_xy(&self, idx: usize) -> (i32, i32) {
         let x = idx as i32 % self.width;
         let y = idx as i32 / self.width;
@@ -66,8 +146,9 @@ impl Map {
         (y * self.width + x) as usize
     }
 
-    fn wall_texture(&self, x: i32, y: i32) -> usize {
-        if x < 1 || x > self.width - 2 || y < 1 || y > self.height - 2 as i32 {
+    /// Sprite of the wall at a position, picked from the walls around it.
+    pub fn wall_texture(&self, x: i32, y: i32) -> usize {
+        if x < 1 || x > self.width - 2 || y < 1 || y > self.height - 2 {
             return 0;
         }
         let mut mask: u8 = 0;
@@ -117,8 +198,42 @@ impl Map {
         if x < 1 || x > self.width - 1 || y < 1 || y > self.height - 1 {
             return false;
         }
-        let idx = self.xy_idx(x, y);
-        self.tiles[idx] == Some(TileType::Floor)
+        self.is_walkable(self.xy_idx(x, y))
+    }
+
+    pub fn is_walkable(&self, idx: usize) -> bool {
+        self.tiles[idx].as_ref().is_some_and(TileType::is_walkable)
+    }
+
+    /// Whether a tile blocks the sight, unset tiles do.
+    pub fn is_opaque(&self, idx: usize) -> bool {
+        self.tiles[idx].as_ref().is_none_or(TileType::is_opaque)
+    }
+
+    /// Map read back from its `rows`, the first row being `y = 0`: `.` floor, `#` wall, `+` and
+    /// `/` closed and open doors, `<` the start and `>` the exit, anything else is empty.
+    pub fn from_rows(rows: &[&str]) -> Self {
+        let mut map = Map::new(rows[0].len() as i32, rows.len() as i32);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, glyph) in row.chars().enumerate() {
+                let idx = map.xy_idx(x as i32, y as i32);
+                map.tiles[idx] = match glyph {
+                    '.' => Some(TileType::Floor),
+                    '#' => Some(TileType::Wall),
+                    '<' => Some(TileType::UpStairs),
+                    '>' => Some(TileType::DownStairs),
+                    '+' => Some(TileType::Door { open: false }),
+                    '/' => Some(TileType::Door { open: true }),
+                    _ => None,
+                };
+                match glyph {
+                    '<' => map.start = Some(idx),
+                    '>' => map.exit = Some(idx),
+                    _ => {}
+                }
+            }
+        }
+        map
     }
 }
 
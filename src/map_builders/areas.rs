@@ -0,0 +1,156 @@
+use std::collections::VecDeque;
+
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+use crate::{analysis::ORTHOGONAL, map::Map};
+
+use super::MapBuilder;
+
+/// Walkable tiles in an area on average.
+pub const AREA_SIZE: usize = 64;
+/// Times the seeds are moved to the middle of their area, evening out the sizes.
+const RELAXATIONS: usize = 3;
+
+/// Splits the walkable tiles of a map without rooms into areas of about `area_size` tiles,
+/// stored in `Map::areas`.
+pub fn split_areas(builder: &mut dyn MapBuilder, area_size: usize, seed: u64) {
+    builder.start_phase("split areas");
+    let map = builder.get_map_mut();
+    let mut rng = StdRng::seed_from_u64(seed);
+    map.areas = voronoi_areas(map, area_size, &mut rng);
+    let count = map.areas.iter().flatten().max().map_or(0, |id| id + 1);
+
+    builder.add_note(&format!("{} areas", count));
+    builder.take_snapshot("split areas");
+    builder.end_phase();
+}
+
+/// Area of every tile, `None` for tiles that aren't walkable.
+///
+/// Random walkable tiles are picked as seeds and every walkable tile joins the closest one,
+/// walking around walls so areas never span two regions. Each seed is then moved to the tile
+/// of its area closest to its center and the areas are grown again.
+pub fn voronoi_areas(map: &Map, area_size: usize, rng: &mut StdRng) -> Vec<Option<usize>> {
+    let floor: Vec<usize> = (0..map.tiles.len())
+        .filter(|idx| map.is_walkable(*idx))
+        .collect();
+    let count = (floor.len() / area_size.max(1)).max(1);
+    let mut seeds: Vec<usize> = floor.choose_multiple(rng, count).copied().collect();
+
+    let mut areas = grow_areas(map, &seeds);
+    for _ in 0..RELAXATIONS {
+        seeds = centers(map, &areas);
+        areas = grow_areas(map, &seeds);
+    }
+    areas
+}
+
+/// Grows an area from every seed at the same pace, regions without a seed become areas of
+/// their own.
+fn grow_areas(map: &Map, seeds: &[usize]) -> Vec<Option<usize>> {
+    let mut areas = vec![None; map.tiles.len()];
+    let mut queue = VecDeque::new();
+    for (id, seed) in seeds.iter().enumerate() {
+        areas[*seed] = Some(id);
+        queue.push_back(*seed);
+    }
+
+    let mut next_id = seeds.len();
+    for start in 0..map.tiles.len() {
+        if queue.is_empty() {
+            if !map.is_walkable(start) || areas[start].is_some() {
+                continue;
+            }
+            areas[start] = Some(next_id);
+            queue.push_back(start);
+            next_id += 1;
+        }
+
+        while let Some(idx) = queue.pop_front() {
+            let (x, y) = map.idx_xy(idx);
+            for (nx, ny) in ORTHOGONAL.iter().map(|(dx, dy)| (x + dx, y + dy)) {
+                if nx < 0 || nx >= map.width || ny < 0 || ny >= map.height {
+                    continue;
+                }
+                let next = map.xy_idx(nx, ny);
+                if map.is_walkable(next) && areas[next].is_none() {
+                    areas[next] = areas[idx];
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+    areas
+}
+
+/// Tile of every area closest to the average position of its tiles.
+pub(crate) fn centers(map: &Map, areas: &[Option<usize>]) -> Vec<usize> {
+    let count = areas.iter().flatten().max().map_or(0, |id| id + 1);
+    let mut sums = vec![(0., 0., 0.); count];
+    for (idx, id) in areas.iter().enumerate() {
+        if let Some(id) = id {
+            let (x, y) = map.idx_xy(idx);
+            let sum = &mut sums[*id];
+            *sum = (sum.0 + x as f32, sum.1 + y as f32, sum.2 + 1.);
+        }
+    }
+
+    let mut centers: Vec<(f32, usize)> = vec![(f32::MAX, 0); count];
+    for (idx, id) in areas.iter().enumerate() {
+        if let Some(id) = id {
+            let (x, y) = map.idx_xy(idx);
+            let (sx, sy, n) = sums[*id];
+            let distance = (x as f32 - sx / n).powi(2) + (y as f32 - sy / n).powi(2);
+            if distance < centers[*id].0 {
+                centers[*id] = (distance, idx);
+            }
+        }
+    }
+    centers.into_iter().map(|(_, idx)| idx).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn areas(rows: &[&str], area_size: usize) -> (Map, Vec<Option<usize>>) {
+        let map = Map::from_rows(rows);
+        let areas = voronoi_areas(&map, area_size, &mut StdRng::seed_from_u64(1));
+        (map, areas)
+    }
+
+    #[test]
+    fn maps_without_floor_have_no_areas() {
+        let (_, areas) = areas(&["###", "###"], 4);
+        assert!(areas.iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn a_room_smaller_than_an_area_is_a_single_area() {
+        let (map, areas) = areas(&["#####", "#...#", "#...#", "#####"], 64);
+        for (idx, area) in areas.iter().enumerate() {
+            assert_eq!(*area, map.is_walkable(idx).then_some(0));
+        }
+        // Ties go to the first tile
+        assert_eq!(centers(&map, &areas), vec![map.xy_idx(2, 1)]);
+    }
+
+    #[test]
+    fn regions_without_a_seed_become_areas_of_their_own() {
+        let (map, areas) = areas(&["#########", "#...#...#", "#########"], 64);
+        let left = areas[map.xy_idx(1, 1)].unwrap();
+        let right = areas[map.xy_idx(5, 1)].unwrap();
+        assert_ne!(left, right);
+        assert!((1..4).all(|x| areas[map.xy_idx(x, 1)] == Some(left)));
+        assert!((5..8).all(|x| areas[map.xy_idx(x, 1)] == Some(right)));
+    }
+
+    #[test]
+    fn areas_of_one_tile_split_every_tile() {
+        let (map, areas) = areas(&["######", "#....#", "######"], 1);
+        let mut ids: Vec<usize> = areas.iter().flatten().copied().collect();
+        ids.sort_unstable();
+        assert_eq!(ids, vec![0, 1, 2, 3]);
+        assert_eq!(centers(&map, &areas).len(), 4);
+    }
+}
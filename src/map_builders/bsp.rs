@@ -1,127 +1,231 @@
 use std::collections::VecDeque;
 
-use rand::{rngs::ThreadRng, Rng};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
 use crate::{
     map::{Map, TileType},
-    map_builders::common::apply_room_to_map,
+    map_builders::common::{apply_room_to_map, place_start_in_room},
 };
 
-use super::{MapBuilder, Rect};
+use super::{
+    error::{check_settings, check_size},
+    BspTree, Field, GenerationError, MapBuilder, Overlay, Rect, Settings, Snapshot,
+    SnapshotHistory, SnapshotPolicy, Unit,
+};
+
+/// Space kept between the partitions and the right and bottom borders.
+const PADDING: i32 = 5;
+
+pub struct BspSettings {
+    /// Subdivisions tried, rooms that don't fit are skipped.
+    pub max_rooms: i32,
+}
+
+impl Default for BspSettings {
+    fn default() -> Self {
+        Self { max_rooms: 240 }
+    }
+}
+
+impl Settings for BspSettings {
+    const FIELDS: &'static [Field<Self>] = &[Field {
+        key: "max_rooms",
+        name: "Max rooms",
+        unit: Unit::Count,
+        min: 1,
+        max: 350,
+        get: |s| s.max_rooms,
+        set: |s, value| s.max_rooms = value,
+    }];
+}
 
 #[derive(Default)]
 pub struct BspMap {
     map: Map,
     rooms: Vec<Rect>,
-    rects: Vec<Rect>,
-    snapshots: VecDeque<Map>,
+    tree: BspTree,
+    overlay: Overlay,
+    snapshots: SnapshotHistory,
+    settings: BspSettings,
 }
 
 impl MapBuilder for BspMap {
-    fn build_map(&mut self, width: i32, height: i32, options: &[crate::algorithms::Option]) {
+    fn build_map(&mut self, width: i32, height: i32, seed: u64) -> Result<(), GenerationError> {
+        check_settings(&self.settings)?;
+        check_size(width, height, PADDING + 1, PADDING + 1)?;
         self.map = Map::new(width, height);
-        self.build(options[0].value);
+        self.overlay = Overlay::default();
+        self.snapshots.clear();
+        self.rooms.clear();
+        self.tree = BspTree::default();
+        self.build(self.settings.max_rooms, seed);
+        if self.rooms.is_empty() {
+            return Err(GenerationError::NoRoom);
+        }
+        Ok(())
     }
     fn get_map(&self) -> Map {
         self.map.clone()
     }
-    fn get_snapshot_history(&self) -> VecDeque<Map> {
-        self.snapshots.clone()
+
+    fn get_map_mut(&mut self) -> &mut Map {
+        &mut self.map
+    }
+    fn get_snapshot_history(&self) -> VecDeque<Snapshot> {
+        self.snapshots.history()
+    }
+    fn start_phase(&mut self, phase: &str) {
+        self.snapshots.start_phase(&self.map, &self.overlay, phase);
+    }
+
+    fn take_snapshot(&mut self, label: &str) {
+        self.snapshots.push(&self.map, &self.overlay, label);
     }
-    fn take_snapshot(&mut self) {
-        self.snapshots.push_back(self.map.clone());
+
+    fn add_note(&mut self, note: &str) {
+        self.snapshots.add_note(note);
+    }
+
+    fn end_phase(&mut self) {
+        self.snapshots.end_phase(&self.map, &self.overlay);
+    }
+
+    fn set_snapshot_policy(&mut self, policy: SnapshotPolicy) {
+        self.snapshots.set_policy(policy);
+    }
+
+    fn get_rooms(&self) -> Option<Vec<Rect>> {
+        Some(self.rooms.clone())
     }
 }
 
 impl BspMap {
-    fn build(&mut self, rooms: i32) {
+    pub fn new(settings: BspSettings) -> Self {
+        Self {
+            settings,
+            ..Default::default()
+        }
+    }
+
+    pub fn tree(&self) -> &BspTree {
+        &self.tree
+    }
+
+    fn build(&mut self, rooms: i32, seed: u64) {
+        self.start_phase("fill walls");
         self.map.tiles.fill(Some(TileType::Wall));
-        self.take_snapshot();
-        let rng = &mut rand::thread_rng();
+        self.take_snapshot("fill walls");
+        let rng = &mut StdRng::seed_from_u64(seed);
 
-        const PADDING: i32 = 5;
-        self.rects.clear();
-        self.rects.push(Rect::new(
+        self.tree = BspTree::new(Rect::new(
             0,
             0,
             self.map.width - PADDING,
             self.map.height - PADDING,
         ));
 
-        let first_room = self.rects[0];
-        self.add_subrects(first_room);
+        self.add_subrects(self.tree.root());
+        self.overlay.partitions = self.partitions();
 
+        self.start_phase("place rooms");
+        let mut rejected = 0;
         let mut n_rooms = 0;
         while n_rooms < rooms {
-            let rect = self.get_random_rect(rng);
-            let candidate = self.get_random_sub_rect(rect, rng);
+            let node = self.get_random_leaf(rng);
+            let candidate = self.get_random_sub_rect(self.tree.node(node).rect, rng);
 
-            if self.is_possible(candidate) {
+            if self.is_possible(candidate) && self.tree.set_room(node, candidate) {
                 apply_room_to_map(&mut self.map, &candidate);
                 self.rooms.push(candidate);
-                self.add_subrects(rect);
-                self.take_snapshot();
+                self.add_subrects(node);
+                self.overlay.room_centers.push(candidate.center());
+                self.overlay.partitions = self.partitions();
+                if rejected > 0 {
+                    self.add_note(&format!("{} overlapping candidates rejected", rejected));
+                    rejected = 0;
+                }
+                self.take_snapshot(&format!("place room {}", self.rooms.len()));
+            } else {
+                self.overlay.rejected_rooms.push(candidate);
+                rejected += 1;
             }
 
             n_rooms += 1;
         }
+        self.end_phase();
 
         if self.rooms.is_empty() {
             // No rooms were created, so we can't make corridors
             return;
         }
 
-        self.rooms.sort_by(|a, b| a.x1.cmp(&b.x1));
-        // Now we want corridors
-        for i in 0..self.rooms.len() - 1 {
-            let room = self.rooms[i];
-            let next_room = self.rooms[i + 1];
+        self.rooms.sort_by_key(|r| r.x1);
+        // Now we want corridors, between the rooms of sibling partitions first
+        self.start_phase("dig corridors");
+        for (from, to) in self.tree.links() {
+            let (room, next_room) = match (self.tree.node(from).room, self.tree.node(to).room) {
+                (Some(room), Some(next_room)) => (room, next_room),
+                _ => continue,
+            };
             let start_x = rng.gen_range(room.x1 + 1..room.x2);
             let start_y = rng.gen_range(room.y1 + 1..room.y2);
             let end_x = rng.gen_range(next_room.x1 + 1..next_room.x2);
             let end_y = rng.gen_range(next_room.y1 + 1..next_room.y2);
 
             self.draw_corridor(start_x, start_y, end_x, end_y);
-            self.take_snapshot();
+            self.overlay
+                .corridors
+                .push(((start_x, start_y), (end_x, end_y)));
+            self.take_snapshot(&format!("join partition {} to partition {}", from, to));
         }
+        self.end_phase();
+
+        place_start_in_room(&mut self.map, &self.rooms);
     }
 
-    fn add_subrects(&mut self, rect: Rect) {
+    fn partitions(&self) -> Vec<Rect> {
+        self.tree.nodes().iter().map(|node| node.rect).collect()
+    }
+
+    fn add_subrects(&mut self, node: usize) {
+        // Partitions are only split once, their children get picked on their own
+        if !self.tree.is_leaf(node) {
+            return;
+        }
+
+        let rect = self.tree.node(node).rect;
         let width = i32::abs(rect.x1 - rect.x2);
         let height = i32::abs(rect.y1 - rect.y2);
         let half_width = i32::max(width / 2, 1);
         let half_height = i32::max(height / 2, 1);
 
-        self.rects
-            .push(Rect::new(rect.x1, rect.y1, half_width, half_height));
-        self.rects.push(Rect::new(
-            rect.x1,
-            rect.y1 + half_height,
-            half_width,
-            half_height,
-        ));
-        self.rects.push(Rect::new(
-            rect.x1 + half_width,
-            rect.y1,
-            half_width,
-            half_height,
-        ));
-        self.rects.push(Rect::new(
-            rect.x1 + half_width,
-            rect.y1 + half_height,
-            half_width,
-            half_height,
-        ));
+        self.tree
+            .add_child(node, Rect::new(rect.x1, rect.y1, half_width, half_height));
+        self.tree.add_child(
+            node,
+            Rect::new(rect.x1, rect.y1 + half_height, half_width, half_height),
+        );
+        self.tree.add_child(
+            node,
+            Rect::new(rect.x1 + half_width, rect.y1, half_width, half_height),
+        );
+        self.tree.add_child(
+            node,
+            Rect::new(
+                rect.x1 + half_width,
+                rect.y1 + half_height,
+                half_width,
+                half_height,
+            ),
+        );
     }
-    fn get_random_rect(&mut self, rng: &mut ThreadRng) -> Rect {
-        if self.rects.len() == 1 {
-            return self.rects[0];
-        }
-        let idx = (rng.gen_range(0..self.rects.len() as i32)) as usize;
-        self.rects[idx]
+    /// Rooms are only placed in partitions that haven't been split, which have no room yet.
+    fn get_random_leaf(&self, rng: &mut StdRng) -> usize {
+        let leaves = self.tree.leaves();
+        leaves[rng.gen_range(0..leaves.len())]
     }
 
-    fn get_random_sub_rect(&self, rect: Rect, rng: &mut ThreadRng) -> Rect {
+    fn get_random_sub_rect(&self, rect: Rect, rng: &mut StdRng) -> Rect {
         let mut result = rect;
         let rect_width = i32::abs(rect.x1 - rect.x2);
         let rect_height = i32::abs(rect.y1 - rect.y2);
@@ -1,52 +1,148 @@
 use std::collections::VecDeque;
 
-use rand::{rngs::ThreadRng, Rng};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
 use crate::map::{Map, TileType};
 
-use super::{MapBuilder, Rect};
+use super::{
+    common::place_start_in_room,
+    error::{check_settings, check_size},
+    BspTree, Field, GenerationError, MapBuilder, Overlay, Rect, Settings, Snapshot,
+    SnapshotHistory, SnapshotPolicy, Unit,
+};
+
+pub struct BspInteriorSettings {
+    /// Partitions aren't split once they're this small.
+    pub min_room_size: i32,
+    /// Chance of splitting a partition vertically instead of horizontally.
+    pub vertical_split_percent: i32,
+}
+
+impl Default for BspInteriorSettings {
+    fn default() -> Self {
+        Self {
+            min_room_size: 6,
+            vertical_split_percent: 50,
+        }
+    }
+}
+
+impl Settings for BspInteriorSettings {
+    const FIELDS: &'static [Field<Self>] = &[
+        Field {
+            key: "min_room_size",
+            name: "Min room size",
+            unit: Unit::Tiles,
+            min: 6,
+            max: 100,
+            get: |s| s.min_room_size,
+            set: |s, value| s.min_room_size = value,
+        },
+        Field {
+            key: "vertical_split",
+            name: "Vertical split percentage",
+            unit: Unit::Percent,
+            min: 1,
+            max: 100,
+            get: |s| s.vertical_split_percent,
+            set: |s, value| s.vertical_split_percent = value,
+        },
+    ];
+}
 
 #[derive(Default)]
 pub struct BspInteriorMap {
     map: Map,
     rooms: Vec<Rect>,
-    rects: Vec<Rect>,
-    snapshots: VecDeque<Map>,
+    tree: BspTree,
+    overlay: Overlay,
+    snapshots: SnapshotHistory,
+    settings: BspInteriorSettings,
 }
 
 impl MapBuilder for BspInteriorMap {
-    fn build_map(&mut self, width: i32, height: i32, options: &[crate::algorithms::Option]) {
+    fn build_map(&mut self, width: i32, height: i32, seed: u64) -> Result<(), GenerationError> {
+        check_settings(&self.settings)?;
+        // The first split must leave rooms at least two tiles wide
+        check_size(width, height, 8, 8)?;
         self.map = Map::new(width, height);
-        self.build(options[0].value, options[1].value as f64 / 100.);
+        self.overlay = Overlay::default();
+        self.snapshots.clear();
+        self.rooms.clear();
+        self.tree = BspTree::default();
+        self.build(
+            self.settings.min_room_size,
+            self.settings.vertical_split_percent as f64 / 100.,
+            seed,
+        );
+        Ok(())
     }
     fn get_map(&self) -> Map {
         self.map.clone()
     }
-    fn get_snapshot_history(&self) -> VecDeque<Map> {
-        self.snapshots.clone()
+
+    fn get_map_mut(&mut self) -> &mut Map {
+        &mut self.map
+    }
+    fn get_snapshot_history(&self) -> VecDeque<Snapshot> {
+        self.snapshots.history()
     }
-    fn take_snapshot(&mut self) {
-        self.snapshots.push_back(self.map.clone());
+    fn start_phase(&mut self, phase: &str) {
+        self.snapshots.start_phase(&self.map, &self.overlay, phase);
+    }
+
+    fn take_snapshot(&mut self, label: &str) {
+        self.snapshots.push(&self.map, &self.overlay, label);
+    }
+
+    fn add_note(&mut self, note: &str) {
+        self.snapshots.add_note(note);
+    }
+
+    fn end_phase(&mut self) {
+        self.snapshots.end_phase(&self.map, &self.overlay);
+    }
+
+    fn set_snapshot_policy(&mut self, policy: SnapshotPolicy) {
+        self.snapshots.set_policy(policy);
+    }
+
+    fn get_rooms(&self) -> Option<Vec<Rect>> {
+        Some(self.rooms.clone())
     }
 }
 
 impl BspInteriorMap {
-    fn build(&mut self, min_room_size: i32, split_chance: f64) {
-        self.map.tiles.fill(Some(TileType::Wall));
-        self.take_snapshot();
-        let rng = &mut rand::thread_rng();
+    pub fn new(settings: BspInteriorSettings) -> Self {
+        Self {
+            settings,
+            ..Default::default()
+        }
+    }
+
+    pub fn tree(&self) -> &BspTree {
+        &self.tree
+    }
 
-        self.rects.clear();
-        self.rects
-            .push(Rect::new(1, 1, self.map.width - 2, self.map.height - 2));
+    fn build(&mut self, min_room_size: i32, split_chance: f64, seed: u64) {
+        self.start_phase("fill walls");
+        self.map.tiles.fill(Some(TileType::Wall));
+        self.take_snapshot("fill walls");
+        let rng = &mut StdRng::seed_from_u64(seed);
 
-        let first_room = self.rects[0];
-        self.add_subrects(first_room, rng, min_room_size, split_chance);
+        self.tree = BspTree::new(Rect::new(1, 1, self.map.width - 2, self.map.height - 2));
+        self.add_subrects(self.tree.root(), rng, min_room_size, split_chance);
 
-        let rooms = self.rects.clone();
-        for r in rooms.iter() {
-            let room = *r;
+        // Every leaf of the tree becomes a room
+        self.start_phase("carve rooms");
+        for leaf in self.tree.leaves() {
+            let room = self.tree.node(leaf).rect;
+            if !self.tree.set_room(leaf, room) {
+                continue;
+            }
             self.rooms.push(room);
+            self.overlay.partitions.push(room);
+            self.overlay.room_centers.push(room.center());
             for y in room.y1..room.y2 {
                 for x in room.x1..room.x2 {
                     let idx = self.map.xy_idx(x, y);
@@ -55,17 +151,22 @@ impl BspInteriorMap {
                     }
                 }
             }
-            self.take_snapshot();
+            self.take_snapshot(&format!("carve room {}", self.rooms.len()));
         }
+        self.end_phase();
 
         if self.rooms.is_empty() {
             // No rooms were created, so we can't make corridors
             return;
         }
 
-        for i in 0..self.rooms.len() - 1 {
-            let room = self.rooms[i];
-            let next_room = self.rooms[i + 1];
+        // Rooms of sibling partitions are joined first, then the partitions above them
+        self.start_phase("dig corridors");
+        for (from, to) in self.tree.links() {
+            let (room, next_room) = match (self.tree.node(from).room, self.tree.node(to).room) {
+                (Some(room), Some(next_room)) => (room, next_room),
+                _ => continue,
+            };
             let start_x = room.x1 + (rng.gen_range(1..i32::abs(room.x1 - room.x2)) - 1);
             let start_y = room.y1 + (rng.gen_range(1..i32::abs(room.y1 - room.y2)) - 1);
             let end_x =
@@ -73,21 +174,24 @@ impl BspInteriorMap {
             let end_y =
                 next_room.y1 + (rng.gen_range(1..i32::abs(next_room.y1 - next_room.y2)) - 1);
             self.draw_corridor(start_x, start_y, end_x, end_y);
-            self.take_snapshot();
+            self.overlay
+                .corridors
+                .push(((start_x, start_y), (end_x, end_y)));
+            self.take_snapshot(&format!("join partition {} to partition {}", from, to));
         }
+        self.end_phase();
+
+        place_start_in_room(&mut self.map, &self.rooms);
     }
 
     fn add_subrects(
         &mut self,
-        rect: Rect,
-        rng: &mut ThreadRng,
+        node: usize,
+        rng: &mut StdRng,
         min_room_size: i32,
         split_chance: f64,
     ) {
-        // Remove the last rect from the list
-        if !self.rects.is_empty() {
-            self.rects.remove(self.rects.len() - 1);
-        }
+        let rect = self.tree.node(node).rect;
 
         // Calculate boundaries
         let width = rect.x2 - rect.x1;
@@ -99,25 +203,31 @@ impl BspInteriorMap {
 
         if split {
             // Horizontal split
-            let h1 = Rect::new(rect.x1, rect.y1, half_width - 1, height);
-            self.rects.push(h1);
+            let h1 = self
+                .tree
+                .add_child(node, Rect::new(rect.x1, rect.y1, half_width - 1, height));
             if half_width > min_room_size {
                 self.add_subrects(h1, rng, min_room_size, split_chance);
             }
-            let h2 = Rect::new(rect.x1 + half_width, rect.y1, half_width, height);
-            self.rects.push(h2);
+            let h2 = self.tree.add_child(
+                node,
+                Rect::new(rect.x1 + half_width, rect.y1, half_width, height),
+            );
             if half_width > min_room_size {
                 self.add_subrects(h2, rng, min_room_size, split_chance);
             }
         } else {
             // Vertical split
-            let v1 = Rect::new(rect.x1, rect.y1, width, half_height - 1);
-            self.rects.push(v1);
+            let v1 = self
+                .tree
+                .add_child(node, Rect::new(rect.x1, rect.y1, width, half_height - 1));
             if half_height > min_room_size {
                 self.add_subrects(v1, rng, min_room_size, split_chance);
             }
-            let v2 = Rect::new(rect.x1, rect.y1 + half_height, width, half_height);
-            self.rects.push(v2);
+            let v2 = self.tree.add_child(
+                node,
+                Rect::new(rect.x1, rect.y1 + half_height, width, half_height),
+            );
             if half_height > min_room_size {
                 self.add_subrects(v2, rng, min_room_size, split_chance);
             }
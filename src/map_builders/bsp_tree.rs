@@ -0,0 +1,245 @@
+use super::Rect;
+
+/// A single partition of a [`BspTree`].
+#[derive(Clone)]
+pub struct BspNode {
+    pub rect: Rect,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    /// Room carved inside this partition, if any.
+    pub room: Option<Rect>,
+}
+
+/// Space partition tree built by the BSP builders.
+///
+/// Nodes are stored in a flat list and reference each other by index, the root is always `0`.
+#[derive(Default, Clone)]
+pub struct BspTree {
+    nodes: Vec<BspNode>,
+}
+
+impl BspTree {
+    pub fn new(root: Rect) -> Self {
+        Self {
+            nodes: vec![BspNode {
+                rect: root,
+                parent: None,
+                children: vec![],
+                room: None,
+            }],
+        }
+    }
+
+    pub fn root(&self) -> usize {
+        0
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn node(&self, idx: usize) -> &BspNode {
+        &self.nodes[idx]
+    }
+
+    pub fn nodes(&self) -> &[BspNode] {
+        &self.nodes
+    }
+
+    pub fn is_leaf(&self, idx: usize) -> bool {
+        self.nodes[idx].children.is_empty()
+    }
+
+    /// Adds a child partition to `parent` and returns its index.
+    pub fn add_child(&mut self, parent: usize, rect: Rect) -> usize {
+        let idx = self.nodes.len();
+        self.nodes.push(BspNode {
+            rect,
+            parent: Some(parent),
+            children: vec![],
+            room: None,
+        });
+        self.nodes[parent].children.push(idx);
+        idx
+    }
+
+    /// Carves `room` in a partition, returns `false` and keeps the room it already has if any.
+    pub fn set_room(&mut self, idx: usize, room: Rect) -> bool {
+        if self.nodes[idx].room.is_some() {
+            return false;
+        }
+        self.nodes[idx].room = Some(room);
+        true
+    }
+
+    /// Returns the leaves in depth first order.
+    pub fn leaves(&self) -> Vec<usize> {
+        let mut leaves = vec![];
+        if self.nodes.is_empty() {
+            return leaves;
+        }
+
+        let mut stack = vec![self.root()];
+        while let Some(idx) = stack.pop() {
+            let node = &self.nodes[idx];
+            if node.children.is_empty() {
+                leaves.push(idx);
+            } else {
+                stack.extend(node.children.iter().rev());
+            }
+        }
+        leaves
+    }
+
+    /// Returns every room of the tree in depth first order.
+    pub fn rooms(&self) -> Vec<Rect> {
+        let mut rooms = vec![];
+        if self.nodes.is_empty() {
+            return rooms;
+        }
+
+        let mut stack = vec![self.root()];
+        while let Some(idx) = stack.pop() {
+            let node = &self.nodes[idx];
+            if let Some(room) = node.room {
+                rooms.push(room);
+            }
+            stack.extend(node.children.iter().rev());
+        }
+        rooms
+    }
+
+    /// Returns the first node holding a room under `idx`, itself included, in depth first order.
+    pub fn room_node(&self, idx: usize) -> Option<usize> {
+        let mut stack = vec![idx];
+        while let Some(idx) = stack.pop() {
+            let node = &self.nodes[idx];
+            if node.room.is_some() {
+                return Some(idx);
+            }
+            stack.extend(node.children.iter().rev());
+        }
+        None
+    }
+
+    /// Returns the pairs of nodes whose rooms must be joined for every room to be connected.
+    ///
+    /// Siblings are joined from the deepest partitions up: under every node, the room of the node
+    /// and a room of each of its children are joined one after the other, so the rooms of a
+    /// partition are connected before it's joined to its siblings.
+    pub fn links(&self) -> Vec<(usize, usize)> {
+        let mut links = vec![];
+        // Children are always added after their parent, going backwards visits them first
+        for idx in (0..self.nodes.len()).rev() {
+            let node = &self.nodes[idx];
+            let groups: Vec<usize> = node
+                .room
+                .map(|_| idx)
+                .into_iter()
+                .chain(
+                    node.children
+                        .iter()
+                        .filter_map(|child| self.room_node(*child)),
+                )
+                .collect();
+            links.extend(groups.windows(2).map(|pair| (pair[0], pair[1])));
+        }
+        links
+    }
+
+    /// Returns the other children of the parent of `idx`.
+    pub fn siblings(&self, idx: usize) -> Vec<usize> {
+        match self.nodes[idx].parent {
+            Some(parent) => self.nodes[parent]
+                .children
+                .iter()
+                .copied()
+                .filter(|&child| child != idx)
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    pub fn depth(&self, idx: usize) -> usize {
+        let mut depth = 0;
+        let mut current = self.nodes[idx].parent;
+        while let Some(parent) = current {
+            depth += 1;
+            current = self.nodes[parent].parent;
+        }
+        depth
+    }
+
+    /// Returns the leaf containing the tile at `x`, `y`.
+    pub fn leaf_at(&self, x: i32, y: i32) -> Option<usize> {
+        if self.nodes.is_empty() || !self.nodes[self.root()].rect.contains(x, y) {
+            return None;
+        }
+
+        let mut current = self.root();
+        while !self.is_leaf(current) {
+            current = *self.nodes[current]
+                .children
+                .iter()
+                .find(|&&child| self.nodes[child].rect.contains(x, y))?;
+        }
+        Some(current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Root split in two, the first child split in two again.
+    fn tree() -> BspTree {
+        let mut tree = BspTree::new(Rect::new(0, 0, 40, 20));
+        let left = tree.add_child(0, Rect::new(0, 0, 20, 20));
+        tree.add_child(0, Rect::new(20, 0, 20, 20));
+        tree.add_child(left, Rect::new(0, 0, 20, 10));
+        tree.add_child(left, Rect::new(0, 10, 20, 10));
+        tree
+    }
+
+    #[test]
+    fn set_room_keeps_the_first_room() {
+        let mut tree = tree();
+        assert!(tree.set_room(2, Rect::new(22, 2, 5, 5)));
+        assert!(!tree.set_room(2, Rect::new(30, 2, 5, 5)));
+        assert_eq!(tree.rooms().len(), 1);
+        assert_eq!(tree.node(2).room.map(|room| room.x1), Some(22));
+    }
+
+    #[test]
+    fn links_join_siblings_before_their_parents() {
+        let mut tree = tree();
+        for leaf in tree.leaves() {
+            let rect = tree.node(leaf).rect;
+            tree.set_room(leaf, Rect::new(rect.x1 + 1, rect.y1 + 1, 3, 3));
+        }
+        assert_eq!(tree.leaves(), vec![3, 4, 2]);
+        assert_eq!(tree.links(), vec![(3, 4), (3, 2)]);
+    }
+
+    #[test]
+    fn links_skip_partitions_without_rooms() {
+        let mut tree = tree();
+        tree.set_room(0, Rect::new(30, 5, 3, 3));
+        tree.set_room(4, Rect::new(2, 12, 3, 3));
+        assert_eq!(tree.links(), vec![(0, 4)]);
+
+        assert!(BspTree::new(Rect::new(0, 0, 10, 10)).links().is_empty());
+    }
+
+    #[test]
+    fn leaf_at_finds_the_deepest_partition() {
+        let tree = tree();
+        assert_eq!(tree.leaf_at(5, 15), Some(4));
+        assert_eq!(tree.leaf_at(25, 5), Some(2));
+        assert_eq!(tree.leaf_at(50, 5), None);
+    }
+}
@@ -1,42 +1,137 @@
 use std::collections::VecDeque;
 
 use bevy::prelude::Vec2;
-use bracket_pathfinding::prelude::DijkstraMap;
-use rand::Rng;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
-use crate::map::{Map, TileType};
+use crate::{
+    analysis::{distance_map, most_distant},
+    map::{Map, TileType},
+};
 
-use super::{common::remove_unreachable_areas_returning_most_distant, MapBuilder};
+use super::{
+    common::closest_floor_to_center,
+    error::{check_settings, check_size},
+    repair_regions, Field, GenerationError, MapBuilder, Overlay, RegionRepair, Settings, Snapshot,
+    SnapshotHistory, SnapshotPolicy, Unit,
+};
+
+pub struct CellularAutomataSettings {
+    /// Chance of a tile starting as floor.
+    pub floor_percent: i32,
+    pub iterations: i32,
+}
+
+impl Default for CellularAutomataSettings {
+    fn default() -> Self {
+        Self {
+            floor_percent: 55,
+            iterations: 15,
+        }
+    }
+}
+
+impl Settings for CellularAutomataSettings {
+    const FIELDS: &'static [Field<Self>] = &[
+        Field {
+            key: "floor_percent",
+            name: "Floor percentage",
+            unit: Unit::Percent,
+            min: 1,
+            max: 100,
+            get: |s| s.floor_percent,
+            set: |s, value| s.floor_percent = value,
+        },
+        Field {
+            key: "iterations",
+            name: "Iterations",
+            unit: Unit::Count,
+            min: 1,
+            max: 100,
+            get: |s| s.iterations,
+            set: |s, value| s.iterations = value,
+        },
+    ];
+}
 
 #[derive(Default)]
 pub struct CellularAutomataBuilder {
     map: Map,
-    snapshots: VecDeque<Map>,
+    overlay: Overlay,
+    snapshots: SnapshotHistory,
+    settings: CellularAutomataSettings,
+    repair: RegionRepair,
 }
 
 impl MapBuilder for CellularAutomataBuilder {
-    fn build_map(&mut self, width: i32, height: i32, options: &[crate::algorithms::Option]) {
+    fn build_map(&mut self, width: i32, height: i32, seed: u64) -> Result<(), GenerationError> {
+        check_settings(&self.settings)?;
+        // Every tile inside the border needs its eight neighbors
+        check_size(width, height, 3, 3)?;
         self.map = Map::new(width, height);
-        self.build(options[0].value as f64 / 100.0, options[1].value);
+        self.overlay = Overlay::default();
+        self.snapshots.clear();
+        self.build(
+            self.settings.floor_percent as f64 / 100.0,
+            self.settings.iterations,
+            seed,
+        )
     }
 
     fn get_map(&self) -> Map {
         self.map.clone()
     }
-    fn get_snapshot_history(&self) -> VecDeque<Map> {
-        self.snapshots.clone()
+
+    fn get_map_mut(&mut self) -> &mut Map {
+        &mut self.map
+    }
+    fn get_snapshot_history(&self) -> VecDeque<Snapshot> {
+        self.snapshots.history()
     }
-    fn take_snapshot(&mut self) {
-        self.snapshots.push_back(self.map.clone());
+    fn start_phase(&mut self, phase: &str) {
+        self.snapshots.start_phase(&self.map, &self.overlay, phase);
+    }
+
+    fn take_snapshot(&mut self, label: &str) {
+        self.snapshots.push(&self.map, &self.overlay, label);
+    }
+
+    fn add_note(&mut self, note: &str) {
+        self.snapshots.add_note(note);
+    }
+
+    fn end_phase(&mut self) {
+        self.snapshots.end_phase(&self.map, &self.overlay);
+    }
+
+    fn set_snapshot_policy(&mut self, policy: SnapshotPolicy) {
+        self.snapshots.set_policy(policy);
+    }
+
+    fn set_region_repair(&mut self, repair: RegionRepair) {
+        self.repair = repair;
     }
 }
 
 impl CellularAutomataBuilder {
-    pub fn build(&mut self, floor_percent: f64, iterations: i32) {
-        let mut rng = rand::thread_rng();
+    pub fn new(settings: CellularAutomataSettings) -> Self {
+        Self {
+            settings,
+            ..Default::default()
+        }
+    }
+
+    pub fn build(
+        &mut self,
+        floor_percent: f64,
+        iterations: i32,
+        seed: u64,
+    ) -> Result<(), GenerationError> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        self.start_phase("fill walls");
         self.map.tiles.fill(Some(TileType::Wall));
-        self.take_snapshot();
+        self.take_snapshot("fill walls");
         // First we completely randomize the map, setting 55% of it to be floor.
+        self.start_phase("randomize");
         for y in 1..self.map.height - 1 {
             for x in 1..self.map.width - 1 {
                 let roll = rng.gen_bool(floor_percent);
@@ -48,10 +143,11 @@ impl CellularAutomataBuilder {
                 }
             }
         }
-        self.take_snapshot();
+        self.take_snapshot("randomize");
 
         // Now we iteratively apply cellular automata rules
-        for _i in 0..iterations {
+        self.start_phase("CA iterations");
+        for i in 0..iterations {
             let mut newtiles = self.map.tiles.clone();
 
             for y in 1..self.map.height - 1 {
@@ -91,23 +187,44 @@ impl CellularAutomataBuilder {
                 }
             }
 
+            let changed = (self.map.tiles.iter())
+                .zip(newtiles.iter())
+                .filter(|(old, new)| old != new)
+                .count();
             self.map.tiles = newtiles.clone();
-            self.take_snapshot();
+            self.add_note(&format!("{} tiles changed", changed));
+            self.take_snapshot(&format!("CA iteration {}", i + 1));
         }
         // Find a starting point; start at the middle and walk left until we find an open tile
+        self.start_phase("place start");
         let mut starting_position =
             Vec2::new(self.map.width as f32 / 2., self.map.height as f32 / 2.);
         let mut start_idx = self
             .map
             .xy_idx(starting_position.x as i32, starting_position.y as i32);
         while self.map.tiles[start_idx] != Some(TileType::Floor) {
+            if starting_position.x < 1. {
+                // The middle row is all wall, use the closest floor instead
+                start_idx = closest_floor_to_center(&self.map).ok_or(GenerationError::NoFloor)?;
+                break;
+            }
             starting_position.x -= 1.;
             start_idx = self
                 .map
                 .xy_idx(starting_position.x as i32, starting_position.y as i32);
         }
-        // Find all tiles we can reach from the starting point
-        remove_unreachable_areas_returning_most_distant(&mut self.map, start_idx);
-        self.take_snapshot();
+        // Caves left unconnected are joined or removed before measuring distances
+        self.map.start = Some(start_idx);
+        let repair = self.repair;
+        repair_regions(self, repair);
+
+        self.start_phase("place exit");
+        let start_idx = self.map.start.ok_or(GenerationError::NoFloor)?;
+        let distances = distance_map(&self.map, start_idx);
+        self.map.exit = most_distant(&distances);
+        self.overlay.distances = Some(distances);
+        self.take_snapshot("distances from start");
+        self.end_phase();
+        Ok(())
     }
 }
@@ -1,6 +1,9 @@
 use bracket_pathfinding::prelude::DijkstraMap;
 
-use crate::map::{Map, TileType};
+use crate::{
+    analysis::{distance_map, most_distant},
+    map::{Map, TileType},
+};
 
 use super::Rect;
 
@@ -13,8 +16,12 @@ pub fn apply_room_to_map(map: &mut Map, room: &Rect) {
     }
 }
 
-/// Searches a map, removes unreachable areas and returns the most distant tile.
-pub fn remove_unreachable_areas_returning_most_distant(map: &mut Map, start_idx: usize) {
+/// Searches a map, removes unreachable areas and returns the most distant tile along with the
+/// distance of every tile to the start.
+pub fn remove_unreachable_areas_returning_most_distant(
+    map: &mut Map,
+    start_idx: usize,
+) -> (usize, Vec<f32>) {
     let map_starts: Vec<usize> = vec![start_idx];
     let dijkstra_map = DijkstraMap::new(
         map.width as usize,
@@ -28,7 +35,7 @@ pub fn remove_unreachable_areas_returning_most_distant(map: &mut Map, start_idx:
         if *tile == Some(TileType::Floor) {
             let distance_to_start = dijkstra_map.map[i];
             // We can't get to this tile - so we'll make it a wall
-            if distance_to_start == std::f32::MAX {
+            if distance_to_start == f32::MAX {
                 *tile = Some(TileType::Wall);
             } else {
                 // If it is further away than our current exit candidate, move the exit
@@ -39,4 +46,35 @@ pub fn remove_unreachable_areas_returning_most_distant(map: &mut Map, start_idx:
             }
         }
     }
+    (exit_tile.0, dijkstra_map.map)
+}
+
+/// Returns the floor tile closest to the center of the map.
+pub fn closest_floor_to_center(map: &Map) -> Option<usize> {
+    let (cx, cy) = (map.width / 2, map.height / 2);
+    map.tiles
+        .iter()
+        .enumerate()
+        .filter(|(_, tile)| **tile == Some(TileType::Floor))
+        .min_by_key(|(idx, _)| {
+            let (x, y) = map.idx_xy(*idx);
+            (x - cx).pow(2) + (y - cy).pow(2)
+        })
+        .map(|(idx, _)| idx)
+}
+
+/// Places the exit on the reachable tile further away from the start.
+pub fn place_exit(map: &mut Map) {
+    if let Some(start) = map.start {
+        map.exit = most_distant(&distance_map(map, start));
+    }
+}
+
+/// Starts the map at the center of the first room and places the exit.
+pub fn place_start_in_room(map: &mut Map, rooms: &[Rect]) {
+    if let Some(room) = rooms.first() {
+        let (x, y) = room.center();
+        map.start = Some(map.xy_idx(x, y));
+        place_exit(map);
+    }
 }
@@ -0,0 +1,241 @@
+use std::{collections::VecDeque, fmt, str::FromStr};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    analysis::{regions, ORTHOGONAL},
+    map::{Map, TileType},
+};
+
+use super::{
+    common::{
+        closest_floor_to_center, place_exit, remove_unreachable_areas_returning_most_distant,
+    },
+    MapBuilder,
+};
+
+/// How disconnected floor regions are handled once a map is built.
+#[derive(Default, PartialEq, Eq, Copy, Clone)]
+pub enum RegionRepair {
+    /// Dig the shortest tunnels joining every region.
+    #[default]
+    Join,
+    /// Remove regions smaller than a number of tiles, then join the rest.
+    Drop(usize),
+    /// Remove everything that can't be reached from the start.
+    KeepReachable,
+}
+
+impl fmt::Display for RegionRepair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RegionRepair::Join => write!(f, "Join regions"),
+            RegionRepair::Drop(_) => write!(f, "Drop small regions"),
+            RegionRepair::KeepReachable => write!(f, "Keep reachable from start"),
+        }
+    }
+}
+
+impl FromStr for RegionRepair {
+    type Err = String;
+
+    /// Parses `join`, `reachable` or `drop:<min size>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "join" => Ok(RegionRepair::Join),
+            None if s == "reachable" => Ok(RegionRepair::KeepReachable),
+            Some(("drop", size)) => size
+                .parse()
+                .map(RegionRepair::Drop)
+                .map_err(|_| format!("Invalid region size '{}'", size)),
+            _ => Err(format!("Unknown region repair '{}'", s)),
+        }
+    }
+}
+
+/// Region repairs are stored by their key.
+impl Serialize for RegionRepair {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.key())
+    }
+}
+
+impl<'de> Deserialize<'de> for RegionRepair {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl RegionRepair {
+    /// Identifier parsed back by `from_str`, as on the command line.
+    pub fn key(&self) -> String {
+        match self {
+            RegionRepair::Join => "join".to_string(),
+            RegionRepair::Drop(size) => format!("drop:{}", size),
+            RegionRepair::KeepReachable => "reachable".to_string(),
+        }
+    }
+
+    pub fn all() -> Vec<Self> {
+        vec![
+            RegionRepair::Join,
+            RegionRepair::Drop(20),
+            RegionRepair::KeepReachable,
+        ]
+    }
+}
+
+/// Makes every floor tile of the built map reachable, snapshotting each change.
+///
+/// The start is moved if it was removed and the exit placed again, as distances change.
+pub fn repair_regions(builder: &mut dyn MapBuilder, repair: RegionRepair) {
+    match repair {
+        RegionRepair::Join => join_regions(builder),
+        RegionRepair::Drop(min_size) => {
+            drop_regions(builder, min_size);
+            join_regions(builder);
+        }
+        RegionRepair::KeepReachable => {
+            builder.start_phase("cull unreachable");
+            let map = builder.get_map_mut();
+            if let Some(start) = map.start.or_else(|| closest_floor_to_center(map)) {
+                remove_unreachable_areas_returning_most_distant(map, start);
+                builder.take_snapshot("cull unreachable");
+            }
+        }
+    }
+
+    builder.end_phase();
+
+    let map = builder.get_map_mut();
+    if map.start.is_none_or(|start| !map.is_walkable(start)) {
+        map.start = closest_floor_to_center(map);
+    }
+    place_exit(map);
+}
+
+/// Removes every region smaller than `min_size`, the biggest one is always kept.
+fn drop_regions(builder: &mut dyn MapBuilder, min_size: usize) {
+    builder.start_phase("drop small regions");
+    let map = builder.get_map_mut();
+    let small: Vec<usize> = regions(map, true)
+        .into_iter()
+        .skip(1)
+        .filter(|region| region.len() < min_size)
+        .flatten()
+        .collect();
+    if small.is_empty() {
+        return;
+    }
+
+    let count = small.len();
+    for idx in small {
+        map.tiles[idx] = Some(TileType::Wall);
+    }
+    builder.take_snapshot(&format!("drop {} tiles", count));
+}
+
+/// Digs the shortest tunnel from the biggest region to the closest one until a single region is
+/// left.
+fn join_regions(builder: &mut dyn MapBuilder) {
+    builder.start_phase("join regions");
+    loop {
+        let map = builder.get_map_mut();
+        let regions = regions(map, true);
+        // Regions touching the border can't be reached without digging through it
+        if regions.len() < 2 || !dig_tunnel(map, &regions[0]) {
+            return;
+        }
+        builder.add_note(&format!("{} regions before digging", regions.len()));
+        builder.take_snapshot("dig tunnel to the closest region");
+    }
+}
+
+/// Digs the shortest tunnel from a tile to the closest walkable tile, unless it's already
+/// connected to one.
+pub fn connect_tile(map: &mut Map, idx: usize) {
+    let (x, y) = map.idx_xy(idx);
+    let connected = map.is_walkable(idx)
+        && ORTHOGONAL.iter().any(|(dx, dy)| {
+            let (nx, ny) = (x + dx, y + dy);
+            nx >= 0
+                && nx < map.width
+                && ny >= 0
+                && ny < map.height
+                && map.is_walkable(map.xy_idx(nx, ny))
+        });
+    if !connected {
+        dig_tunnel(map, &[idx]);
+    }
+}
+
+/// Searches outwards from `from` and turns the tiles leading to the first walkable tile found
+/// into floor. Returns `false` if there's nothing to reach.
+fn dig_tunnel(map: &mut Map, from: &[usize]) -> bool {
+    let mut came_from = vec![None; map.tiles.len()];
+    let mut queue: VecDeque<usize> = from.iter().copied().collect();
+    for idx in from {
+        came_from[*idx] = Some(*idx);
+    }
+    let mut found = None;
+    'search: while let Some(idx) = queue.pop_front() {
+        let (x, y) = map.idx_xy(idx);
+        for (nx, ny) in ORTHOGONAL.iter().map(|(dx, dy)| (x + dx, y + dy)) {
+            // Keep the map border intact
+            if nx < 1 || nx > map.width - 2 || ny < 1 || ny > map.height - 2 {
+                continue;
+            }
+            let next = map.xy_idx(nx, ny);
+            if came_from[next].is_some() {
+                continue;
+            }
+            came_from[next] = Some(idx);
+            if map.is_walkable(next) {
+                found = Some(next);
+                break 'search;
+            }
+            queue.push_back(next);
+        }
+    }
+
+    let mut idx = match found {
+        Some(idx) => idx,
+        None => return false,
+    };
+    while let Some(previous) = came_from[idx] {
+        if !map.is_walkable(previous) {
+            map.tiles[previous] = Some(TileType::Floor);
+        }
+        if previous == idx {
+            break;
+        }
+        idx = previous;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn region_repairs_parse() {
+        let parse = |s: &str| s.parse::<RegionRepair>();
+        assert!(parse("join") == Ok(RegionRepair::Join));
+        assert!(parse("reachable") == Ok(RegionRepair::KeepReachable));
+        assert!(parse("drop:0") == Ok(RegionRepair::Drop(0)));
+        assert!(parse("drop:25") == Ok(RegionRepair::Drop(25)));
+        assert!(parse("drop:-1") == Err("Invalid region size '-1'".to_string()));
+        assert!(parse("drop") == Err("Unknown region repair 'drop'".to_string()));
+        assert!(parse("join:2") == Err("Unknown region repair 'join:2'".to_string()));
+        assert!(parse("").is_err());
+        for repair in [RegionRepair::Drop(0), RegionRepair::Drop(25)]
+            .into_iter()
+            .chain(RegionRepair::all())
+        {
+            assert!(parse(&repair.key()) == Ok(repair));
+        }
+    }
+}
@@ -1,17 +1,26 @@
 use std::collections::VecDeque;
 
 use bevy::prelude::Vec2;
-use rand::Rng;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
-use crate::map::{Map, TileType};
+use crate::{
+    analysis::{distance_map, most_distant},
+    map::{Map, TileType},
+};
 
-use super::{common::remove_unreachable_areas_returning_most_distant, MapBuilder};
+use super::{
+    error::{check_settings, check_size},
+    repair_regions, Field, GenerationError, MapBuilder, Overlay, RegionRepair, Settings, Snapshot,
+    SnapshotHistory, SnapshotPolicy, Unit,
+};
 
 pub struct DrunkardsWalkBuilder {
     map: Map,
-    snapshots: VecDeque<Map>,
+    overlay: Overlay,
+    snapshots: SnapshotHistory,
     starting_position: Vec2,
     settings: DrunkardSettings,
+    repair: RegionRepair,
 }
 
 #[derive(PartialEq, Copy, Clone)]
@@ -22,24 +31,99 @@ pub enum DrunkSpawnMode {
 
 pub struct DrunkardSettings {
     pub spawn_mode: DrunkSpawnMode,
+    /// Steps taken by each digger.
     pub drunken_lifetime: i32,
-    pub floor_percent: f32,
+    /// Digging stops once this share of the map is floor.
+    pub floor_percent: i32,
+}
+
+impl Default for DrunkardSettings {
+    fn default() -> Self {
+        Self {
+            spawn_mode: DrunkSpawnMode::Random,
+            drunken_lifetime: 100,
+            floor_percent: 40,
+        }
+    }
+}
+
+impl Settings for DrunkardSettings {
+    const FIELDS: &'static [Field<Self>] = &[
+        Field {
+            key: "lifetime",
+            name: "Digger lifetime",
+            unit: Unit::Count,
+            min: 1,
+            max: 1000,
+            get: |s| s.drunken_lifetime,
+            set: |s, value| s.drunken_lifetime = value,
+        },
+        Field {
+            key: "floor_percent",
+            name: "Floor percentage",
+            unit: Unit::Percent,
+            min: 1,
+            max: 80,
+            get: |s| s.floor_percent,
+            set: |s, value| s.floor_percent = value,
+        },
+    ];
 }
 
 impl MapBuilder for DrunkardsWalkBuilder {
-    fn build_map(&mut self, width: i32, height: i32, _options: &[crate::algorithms::Option]) {
+    fn build_map(&mut self, width: i32, height: i32, seed: u64) -> Result<(), GenerationError> {
+        check_settings(&self.settings)?;
+        check_size(width, height, 4, 4)?;
+
+        // Diggers never reach the first two and the last rows and columns
+        let diggable = (width - 3) * (height - 3);
+        let max_percent = diggable * 100 / (width * height);
+        if self.settings.floor_percent > max_percent {
+            return Err(GenerationError::FloorUnreachable {
+                percent: self.settings.floor_percent,
+                max_percent,
+            });
+        }
+
         self.map = Map::new(width, height);
-        self.build();
+        self.overlay = Overlay::default();
+        self.snapshots.clear();
+        self.build(seed);
+        Ok(())
     }
 
     fn get_map(&self) -> Map {
         self.map.clone()
     }
-    fn get_snapshot_history(&self) -> VecDeque<Map> {
-        self.snapshots.clone()
+
+    fn get_map_mut(&mut self) -> &mut Map {
+        &mut self.map
+    }
+    fn get_snapshot_history(&self) -> VecDeque<Snapshot> {
+        self.snapshots.history()
     }
-    fn take_snapshot(&mut self) {
-        self.snapshots.push_back(self.map.clone());
+    fn start_phase(&mut self, phase: &str) {
+        self.snapshots.start_phase(&self.map, &self.overlay, phase);
+    }
+
+    fn take_snapshot(&mut self, label: &str) {
+        self.snapshots.push(&self.map, &self.overlay, label);
+    }
+
+    fn add_note(&mut self, note: &str) {
+        self.snapshots.add_note(note);
+    }
+
+    fn end_phase(&mut self) {
+        self.snapshots.end_phase(&self.map, &self.overlay);
+    }
+
+    fn set_snapshot_policy(&mut self, policy: SnapshotPolicy) {
+        self.snapshots.set_policy(policy);
+    }
+
+    fn set_region_repair(&mut self, repair: RegionRepair) {
+        self.repair = repair;
     }
 }
 
@@ -47,16 +131,19 @@ impl DrunkardsWalkBuilder {
     pub fn new(settings: DrunkardSettings) -> DrunkardsWalkBuilder {
         DrunkardsWalkBuilder {
             map: Map::default(),
+            overlay: Overlay::default(),
             starting_position: Vec2::new(0., 0.),
-            snapshots: VecDeque::new(),
+            snapshots: SnapshotHistory::default(),
             settings,
+            repair: RegionRepair::default(),
         }
     }
 
-    pub fn build(&mut self) {
-        let mut rng = rand::thread_rng();
+    pub fn build(&mut self, seed: u64) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        self.start_phase("fill walls");
         self.map.tiles.fill(Some(TileType::Wall));
-        self.take_snapshot();
+        self.take_snapshot("fill walls");
 
         self.starting_position = Vec2::new(self.map.width as f32 / 2., self.map.height as f32 / 2.);
         let start_idx = self.map.xy_idx(
@@ -65,7 +152,8 @@ impl DrunkardsWalkBuilder {
         );
 
         let total_tiles = self.map.width * self.map.height;
-        let desired_floor_tiles = (self.settings.floor_percent * total_tiles as f32) as usize;
+        let floor_ratio = self.settings.floor_percent as f32 / 100.;
+        let desired_floor_tiles = (floor_ratio * total_tiles as f32) as usize;
         let mut floor_tile_count = self
             .map
             .tiles
@@ -74,6 +162,8 @@ impl DrunkardsWalkBuilder {
             .count();
         let mut digger_count = 0;
 
+        self.start_phase("dig");
+
         while floor_tile_count < desired_floor_tiles {
             let mut did_something = false;
             let mut drunk_x;
@@ -128,8 +218,9 @@ impl DrunkardsWalkBuilder {
 
                 drunk_life -= 1;
             }
+            self.overlay.head = Some((drunk_x, drunk_y));
             if did_something {
-                self.take_snapshot();
+                self.take_snapshot(&format!("digger {}", digger_count + 1));
             }
 
             digger_count += 1;
@@ -145,9 +236,22 @@ impl DrunkardsWalkBuilder {
                 .filter(|a| **a == Some(TileType::Floor))
                 .count();
         }
-        self.take_snapshot();
+        self.overlay.head = None;
+
+        // Caves left unconnected are joined or removed before measuring distances
+        self.map.start = Some(start_idx);
+        let repair = self.repair;
+        repair_regions(self, repair);
 
-        remove_unreachable_areas_returning_most_distant(&mut self.map, start_idx);
-        self.take_snapshot();
+        self.start_phase("place exit");
+        let start_idx = match self.map.start {
+            Some(start_idx) => start_idx,
+            None => return,
+        };
+        let distances = distance_map(&self.map, start_idx);
+        self.map.exit = most_distant(&distances);
+        self.overlay.distances = Some(distances);
+        self.take_snapshot("distances from start");
+        self.end_phase();
     }
 }
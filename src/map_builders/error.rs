@@ -0,0 +1,153 @@
+use std::fmt;
+
+use super::Settings;
+
+/// Why a map couldn't be built with the requested parameters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GenerationError {
+    /// No algorithm was selected.
+    NoAlgorithm,
+    /// Settings out of their range or inconsistent with each other.
+    InvalidSettings(Vec<String>),
+    /// The map is smaller than the algorithm needs.
+    MapTooSmall {
+        width: i32,
+        height: i32,
+        min_width: i32,
+        min_height: i32,
+    },
+    /// The biggest room doesn't fit in the map.
+    RoomTooBig { size: i32, width: i32, height: i32 },
+    /// Every room was rejected.
+    NoRoom,
+    /// Nothing but walls was left to start from.
+    NoFloor,
+    /// The map can't have that much floor.
+    FloorUnreachable { percent: i32, max_percent: i32 },
+}
+
+impl fmt::Display for GenerationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GenerationError::NoAlgorithm => write!(f, "No algorithm selected"),
+            GenerationError::InvalidSettings(errors) => write!(f, "{}", errors.join("\n")),
+            GenerationError::MapTooSmall {
+                width,
+                height,
+                min_width,
+                min_height,
+            } => write!(
+                f,
+                "A {}x{} map is too small, the algorithm needs at least {}x{}",
+                width, height, min_width, min_height
+            ),
+            GenerationError::RoomTooBig {
+                size,
+                width,
+                height,
+            } => write!(
+                f,
+                "Rooms of {} tiles don't fit in a {}x{} map",
+                size, width, height
+            ),
+            GenerationError::NoRoom => write!(f, "No room could be placed"),
+            GenerationError::NoFloor => write!(f, "The map has no floor"),
+            GenerationError::FloorUnreachable {
+                percent,
+                max_percent,
+            } => write!(
+                f,
+                "{}% of floor can't be dug in this map, at most {}% can",
+                percent, max_percent
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GenerationError {}
+
+/// Fails if the map is smaller than `min_width` by `min_height`.
+pub fn check_size(
+    width: i32,
+    height: i32,
+    min_width: i32,
+    min_height: i32,
+) -> Result<(), GenerationError> {
+    if width < min_width || height < min_height {
+        return Err(GenerationError::MapTooSmall {
+            width,
+            height,
+            min_width,
+            min_height,
+        });
+    }
+    Ok(())
+}
+
+/// Fails with every reason the settings can't be used, builders may be given any settings.
+pub fn check_settings(settings: &impl Settings) -> Result<(), GenerationError> {
+    let errors = settings.errors();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(GenerationError::InvalidSettings(errors))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_builders::*;
+
+    /// Builds a 60x40 map and returns the reasons it was refused.
+    fn refused(mut builder: impl MapBuilder) -> Vec<String> {
+        match builder.build_map(60, 40, 1) {
+            Err(GenerationError::InvalidSettings(errors)) => errors,
+            Err(err) => panic!("Unexpected error: {}", err),
+            Ok(()) => panic!("Invalid settings were accepted"),
+        }
+    }
+
+    #[test]
+    fn builders_refuse_invalid_settings() {
+        let errors = refused(RoomsMap::new(RoomsSettings {
+            min_room_size: 8,
+            max_room_size: 8,
+            ..Default::default()
+        }));
+        assert_eq!(
+            errors,
+            vec!["Min room size must be smaller than max room size"]
+        );
+
+        let errors = refused(RandomMap::new(RandomSettings { tiles_per_wall: 0 }));
+        assert_eq!(
+            errors,
+            vec!["Tiles per wall must be between 1 and 10, got 0"]
+        );
+
+        refused(BspMap::new(BspSettings { max_rooms: -1 }));
+        refused(BspInteriorMap::new(BspInteriorSettings {
+            min_room_size: 0,
+            ..Default::default()
+        }));
+        refused(CellularAutomataBuilder::new(CellularAutomataSettings {
+            iterations: 1000,
+            ..Default::default()
+        }));
+        refused(DrunkardsWalkBuilder::new(DrunkardSettings {
+            drunken_lifetime: 0,
+            ..Default::default()
+        }));
+    }
+
+    #[test]
+    fn every_range_error_is_reported() {
+        let errors = refused(RoomsMap::new(RoomsSettings {
+            max_rooms: 0,
+            min_room_size: 50,
+            max_room_size: 4,
+        }));
+        assert_eq!(errors.len(), 2);
+    }
+}
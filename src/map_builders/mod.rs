@@ -1,25 +1,58 @@
 use std::collections::VecDeque;
 
-use crate::{algorithms::Option, map::Map};
-pub use bsp::BspMap;
-pub use bsp_interior::BspInteriorMap;
-pub use cellular_automata::CellularAutomataBuilder;
+use crate::map::Map;
+pub(crate) use areas::centers;
+pub use areas::{split_areas, voronoi_areas, AREA_SIZE};
+pub use bsp::{BspMap, BspSettings};
+pub use bsp_interior::{BspInteriorMap, BspInteriorSettings};
+pub use bsp_tree::{BspNode, BspTree};
+pub use cellular_automata::{CellularAutomataBuilder, CellularAutomataSettings};
+pub use common::{place_exit, remove_unreachable_areas_returning_most_distant};
+pub use connectivity::{connect_tile, repair_regions, RegionRepair};
 pub use drunkard_walk::*;
-pub use random::RandomMap;
-pub use rooms::RoomsMap;
+pub use error::GenerationError;
+pub use random::{RandomMap, RandomSettings};
+pub use rooms::{RoomsMap, RoomsSettings};
+pub use settings::{Field, Settings, Unit};
+pub use snapshot::{phases, Overlay, Snapshot, SnapshotHistory, SnapshotPolicy};
+mod areas;
 mod bsp;
 mod bsp_interior;
+mod bsp_tree;
 mod cellular_automata;
 mod common;
+mod connectivity;
 mod drunkard_walk;
+mod error;
 mod random;
 mod rooms;
+mod settings;
+mod snapshot;
 
-pub trait MapBuilder {
-    fn build_map(&mut self, width: i32, height: i32, options: &[Option]);
+pub trait MapBuilder: Send + Sync {
+    /// Builds a new map, the same seed always builds the same map.
+    fn build_map(&mut self, width: i32, height: i32, seed: u64) -> Result<(), GenerationError>;
     fn get_map(&self) -> Map;
-    fn take_snapshot(&mut self);
-    fn get_snapshot_history(&self) -> VecDeque<Map>;
+    fn get_map_mut(&mut self) -> &mut Map;
+    /// Ends the current phase and names the next one, snapshots are grouped by phase.
+    fn start_phase(&mut self, phase: &str);
+    /// Records a step of the algorithm, if the snapshot policy keeps it.
+    fn take_snapshot(&mut self, label: &str);
+    /// Attaches a note to the next snapshot taken.
+    fn add_note(&mut self, note: &str);
+    /// Marks the last snapshot taken as the end of a phase.
+    fn end_phase(&mut self);
+    /// Chooses which snapshots are recorded, every step is by default.
+    fn set_snapshot_policy(&mut self, policy: SnapshotPolicy);
+    /// Chooses how regions left disconnected are repaired at the end of `build_map`, they are
+    /// joined by default. Builders joining all their rooms with corridors have nothing to repair.
+    fn set_region_repair(&mut self, _repair: RegionRepair) {}
+    fn get_snapshot_history(&self) -> VecDeque<Snapshot>;
+
+    /// Rooms placed by the builder, `None` for algorithms without rooms.
+    fn get_rooms(&self) -> std::option::Option<Vec<Rect>> {
+        None
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -45,6 +78,11 @@ impl Rect {
         self.x1 <= other.x2 && self.x2 >= other.x1 && self.y1 <= other.y2 && self.y2 >= other.y1
     }
 
+    // Returns true if the tile is inside this rect, borders included
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x1 && x <= self.x2 && y >= self.y1 && y <= self.y2
+    }
+
     pub fn center(&self) -> (i32, i32) {
         ((self.x1 + self.x2) / 2, (self.y1 + self.y2) / 2)
     }
@@ -1,40 +1,109 @@
 use std::collections::VecDeque;
 
-use crate::{algorithms::Option, map::TileType, Map};
-use rand::Rng;
+use crate::map::{Map, TileType};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::{
+    common::closest_floor_to_center,
+    error::{check_settings, check_size},
+    repair_regions, Field, GenerationError, MapBuilder, Overlay, RegionRepair, Settings, Snapshot,
+    SnapshotHistory, SnapshotPolicy, Unit,
+};
+
+pub struct RandomSettings {
+    /// One tile out of this many becomes a wall.
+    pub tiles_per_wall: i32,
+}
 
-use super::MapBuilder;
+impl Default for RandomSettings {
+    fn default() -> Self {
+        Self { tiles_per_wall: 4 }
+    }
+}
+
+impl Settings for RandomSettings {
+    const FIELDS: &'static [Field<Self>] = &[Field {
+        key: "tiles_per_wall",
+        name: "Tiles per wall",
+        unit: Unit::Count,
+        min: 1,
+        max: 10,
+        get: |s| s.tiles_per_wall,
+        set: |s, value| s.tiles_per_wall = value,
+    }];
+}
 
 #[derive(Default)]
 pub struct RandomMap {
     map: Map,
-    snapshots: VecDeque<Map>,
+    overlay: Overlay,
+    snapshots: SnapshotHistory,
+    settings: RandomSettings,
+    repair: RegionRepair,
 }
 
 impl MapBuilder for RandomMap {
-    fn build_map(&mut self, width: i32, height: i32, options: &[Option]) {
+    fn build_map(&mut self, width: i32, height: i32, seed: u64) -> Result<(), GenerationError> {
+        check_settings(&self.settings)?;
+        // Walls are placed inside the border
+        check_size(width, height, 3, 3)?;
         self.map = Map::new(width, height);
-        self.fill(options[0].value);
+        self.overlay = Overlay::default();
+        self.snapshots.clear();
+        self.fill(seed)
     }
     fn get_map(&self) -> Map {
         self.map.clone()
     }
 
-    fn take_snapshot(&mut self) {
-        self.snapshots.push_back(self.map.clone());
+    fn get_map_mut(&mut self) -> &mut Map {
+        &mut self.map
+    }
+
+    fn start_phase(&mut self, phase: &str) {
+        self.snapshots.start_phase(&self.map, &self.overlay, phase);
+    }
+
+    fn take_snapshot(&mut self, label: &str) {
+        self.snapshots.push(&self.map, &self.overlay, label);
+    }
+
+    fn add_note(&mut self, note: &str) {
+        self.snapshots.add_note(note);
+    }
+
+    fn end_phase(&mut self) {
+        self.snapshots.end_phase(&self.map, &self.overlay);
+    }
+
+    fn set_snapshot_policy(&mut self, policy: SnapshotPolicy) {
+        self.snapshots.set_policy(policy);
+    }
+
+    fn set_region_repair(&mut self, repair: RegionRepair) {
+        self.repair = repair;
     }
 
-    fn get_snapshot_history(&self) -> VecDeque<Map> {
-        self.snapshots.clone()
+    fn get_snapshot_history(&self) -> VecDeque<Snapshot> {
+        self.snapshots.history()
     }
 }
 
 impl RandomMap {
-    fn fill(&mut self, ratio: i32) {
+    pub fn new(settings: RandomSettings) -> Self {
+        Self {
+            settings,
+            ..Default::default()
+        }
+    }
+
+    fn fill(&mut self, seed: u64) -> Result<(), GenerationError> {
+        self.start_phase("fill floor");
         self.map.tiles.fill(Some(TileType::Floor));
-        self.take_snapshot();
+        self.take_snapshot("fill floor");
 
         // Fill the outer edges with walls
+        self.start_phase("build border");
         for x in 0..self.map.width {
             let idx_top = self.map.xy_idx(x, 0);
             let idx_bottom = self.map.xy_idx(x, self.map.height - 1);
@@ -43,7 +112,7 @@ impl RandomMap {
             self.map.tiles[idx_bottom] = Some(TileType::Wall);
         }
 
-        self.take_snapshot();
+        self.take_snapshot("top and bottom walls");
 
         for y in 0..self.map.height {
             let idx_left = self.map.xy_idx(0, y);
@@ -53,21 +122,71 @@ impl RandomMap {
             self.map.tiles[idx_right] = Some(TileType::Wall);
         }
 
-        self.take_snapshot();
+        self.take_snapshot("left and right walls");
 
-        let rand = &mut rand::thread_rng();
+        let rand = &mut StdRng::seed_from_u64(seed);
 
         // Fill the rest of the map with random walls
-        for _i in 0..(self.map.width * self.map.height / ratio) {
-            let x = rand.gen_range(1..self.map.width as i32 - 1);
-            let y = rand.gen_range(1..self.map.height as i32 - 1);
+        self.start_phase("place random walls");
+        for i in 0..(self.map.width * self.map.height / self.settings.tiles_per_wall) {
+            let x = rand.gen_range(1..self.map.width - 1);
+            let y = rand.gen_range(1..self.map.height - 1);
             let idx = self.map.xy_idx(x, y);
 
             self.map.tiles[idx] = Some(TileType::Wall);
-
-            if _i % 10 == 0 {
-                self.take_snapshot();
-            }
+            self.take_snapshot(&format!("place wall {}", i + 1));
         }
+        self.end_phase();
+
+        // Walls can close off parts of the map, the repair also places the exit
+        self.map.start = closest_floor_to_center(&self.map);
+        let repair = self.repair;
+        repair_regions(self, repair);
+        // Walls can also cover every tile inside the border
+        self.map.start.ok_or(GenerationError::NoFloor)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Labels of the snapshots of random walls recorded under a policy.
+    fn walls(policy: SnapshotPolicy) -> Vec<String> {
+        let mut builder = RandomMap::new(RandomSettings::default());
+        builder.set_snapshot_policy(policy);
+        builder.build_map(60, 40, 1).unwrap();
+        builder
+            .get_snapshot_history()
+            .into_iter()
+            .filter(|snapshot| snapshot.phase == "place random walls")
+            .map(|snapshot| snapshot.label)
+            .collect()
+    }
+
+    #[test]
+    fn every_wall_is_a_step_thinned_by_the_policy() {
+        // 600 walls by default
+        let every_step = walls(SnapshotPolicy::EveryStep);
+        assert_eq!(every_step.len(), 600);
+        assert_eq!(every_step[0], "place wall 1");
+        assert_eq!(every_step[599], "place wall 600");
+
+        let every_nth = walls(SnapshotPolicy::EveryNth(100));
+        // Steps are counted from the start of the build, three come before the walls
+        assert_eq!(every_nth.len(), 7);
+        assert_eq!(every_nth[0], "place wall 98");
+        assert_eq!(every_nth.last().unwrap(), "place wall 600");
+
+        assert_eq!(walls(SnapshotPolicy::Phases), vec!["place wall 600"]);
+        assert!(walls(SnapshotPolicy::Off).is_empty());
+    }
+
+    #[test]
+    fn walls_covering_every_tile_leave_no_floor() {
+        // The single tile inside the border gets all nine walls
+        let mut builder = RandomMap::new(RandomSettings { tiles_per_wall: 1 });
+        assert_eq!(builder.build_map(3, 3, 1), Err(GenerationError::NoFloor));
     }
 }
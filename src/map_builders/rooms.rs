@@ -1,52 +1,163 @@
-use rand::Rng;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use std::cmp::{max, min};
 use std::collections::VecDeque;
 
-use crate::algorithms::Option;
 use crate::map::{Map, TileType};
 
-use super::common::apply_room_to_map;
-use super::{MapBuilder, Rect};
+use super::common::{apply_room_to_map, place_start_in_room};
+use super::{
+    error::check_settings, Field, GenerationError, MapBuilder, Overlay, Rect, Settings, Snapshot,
+    SnapshotHistory, SnapshotPolicy, Unit,
+};
+
+pub struct RoomsSettings {
+    /// Rooms tried, the ones overlapping another are rejected.
+    pub max_rooms: i32,
+    pub min_room_size: i32,
+    /// Rooms are always smaller than this.
+    pub max_room_size: i32,
+}
+
+impl Default for RoomsSettings {
+    fn default() -> Self {
+        Self {
+            max_rooms: 5,
+            min_room_size: 4,
+            max_room_size: 10,
+        }
+    }
+}
+
+impl Settings for RoomsSettings {
+    const FIELDS: &'static [Field<Self>] = &[
+        Field {
+            key: "max_rooms",
+            name: "Max rooms",
+            unit: Unit::Count,
+            min: 1,
+            max: 30,
+            get: |s| s.max_rooms,
+            set: |s, value| s.max_rooms = value,
+        },
+        Field {
+            key: "min_room_size",
+            name: "Min room size",
+            unit: Unit::Tiles,
+            min: 3,
+            max: 15,
+            get: |s| s.min_room_size,
+            set: |s, value| s.min_room_size = value,
+        },
+        Field {
+            key: "max_room_size",
+            name: "Max room size",
+            unit: Unit::Tiles,
+            min: 4,
+            max: 20,
+            get: |s| s.max_room_size,
+            set: |s, value| s.max_room_size = value,
+        },
+    ];
+
+    fn check(&self) -> Vec<String> {
+        if self.min_room_size >= self.max_room_size {
+            vec!["Min room size must be smaller than max room size".to_string()]
+        } else {
+            vec![]
+        }
+    }
+}
 
 #[derive(Default)]
 pub struct RoomsMap {
     map: Map,
     rooms: Vec<Rect>,
-    snapshots: VecDeque<Map>,
+    overlay: Overlay,
+    snapshots: SnapshotHistory,
+    settings: RoomsSettings,
 }
 
 impl MapBuilder for RoomsMap {
-    fn build_map(&mut self, width: i32, height: i32, options: &[Option]) {
+    fn build_map(&mut self, width: i32, height: i32, seed: u64) -> Result<(), GenerationError> {
+        check_settings(&self.settings)?;
+        // Rooms are placed a tile away from the border
+        let size = self.settings.max_room_size - 1;
+        if width < size + 3 || height < size + 3 {
+            return Err(GenerationError::RoomTooBig {
+                size,
+                width,
+                height,
+            });
+        }
         self.map = Map::new(width, height);
-        self.fill(options[0].value, options[1].value, options[2].value);
+        self.overlay = Overlay::default();
+        self.snapshots.clear();
+        self.rooms.clear();
+        self.fill(seed);
+        Ok(())
     }
 
     fn get_map(&self) -> Map {
         self.map.clone()
     }
 
-    fn take_snapshot(&mut self) {
-        self.snapshots.push_back(self.map.clone());
+    fn get_map_mut(&mut self) -> &mut Map {
+        &mut self.map
+    }
+
+    fn start_phase(&mut self, phase: &str) {
+        self.snapshots.start_phase(&self.map, &self.overlay, phase);
+    }
+
+    fn take_snapshot(&mut self, label: &str) {
+        self.snapshots.push(&self.map, &self.overlay, label);
     }
 
-    fn get_snapshot_history(&self) -> VecDeque<Map> {
-        self.snapshots.clone()
+    fn add_note(&mut self, note: &str) {
+        self.snapshots.add_note(note);
+    }
+
+    fn end_phase(&mut self) {
+        self.snapshots.end_phase(&self.map, &self.overlay);
+    }
+
+    fn set_snapshot_policy(&mut self, policy: SnapshotPolicy) {
+        self.snapshots.set_policy(policy);
+    }
+
+    fn get_snapshot_history(&self) -> VecDeque<Snapshot> {
+        self.snapshots.history()
+    }
+
+    fn get_rooms(&self) -> std::option::Option<Vec<Rect>> {
+        Some(self.rooms.clone())
     }
 }
 
 impl RoomsMap {
-    fn fill(&mut self, max_rooms: i32, room_min_size: i32, room_max_size: i32) {
+    pub fn new(settings: RoomsSettings) -> Self {
+        Self {
+            settings,
+            ..Default::default()
+        }
+    }
+
+    fn fill(&mut self, seed: u64) {
+        self.start_phase("fill walls");
         self.map.tiles.fill(Some(TileType::Wall));
-        self.take_snapshot();
+        self.take_snapshot("fill walls");
 
-        let mut rng = rand::thread_rng();
+        let mut rng = StdRng::seed_from_u64(seed);
+        let sizes = self.settings.min_room_size..self.settings.max_room_size;
 
-        for _ in 0..max_rooms {
-            let w = rng.gen_range(room_min_size..room_max_size);
-            let h = rng.gen_range(room_min_size..room_max_size);
+        self.start_phase("place rooms");
+        let mut rejected = 0;
+        for _ in 0..self.settings.max_rooms {
+            let w = rng.gen_range(sizes.clone());
+            let h = rng.gen_range(sizes.clone());
 
-            let x = rng.gen_range(1..self.map.width as i32 - w - 1) - 1;
-            let y = rng.gen_range(1..self.map.height as i32 - h - 1) - 1;
+            let x = rng.gen_range(1..self.map.width - w - 1) - 1;
+            let y = rng.gen_range(1..self.map.height - h - 1) - 1;
 
             let new_room = Rect::new(x, y, w, h);
             let mut ok = true;
@@ -57,35 +168,52 @@ impl RoomsMap {
             }
             if ok {
                 apply_room_to_map(&mut self.map, &new_room);
-                self.take_snapshot();
+                self.overlay.room_centers.push(new_room.center());
+                if rejected > 0 {
+                    self.add_note(&format!("{} overlapping candidates rejected", rejected));
+                    rejected = 0;
+                }
+                self.take_snapshot(&format!("place room {}", self.rooms.len() + 1));
                 self.rooms.push(new_room);
+            } else {
+                self.overlay.rejected_rooms.push(new_room);
+                rejected += 1;
             }
         }
+
         // Add corridors
-        self.rooms.sort_by(|a, b| a.x1.cmp(&b.x1));
+        self.start_phase("dig corridors");
+        self.rooms.sort_by_key(|r| r.x1);
 
         for i in 0..self.rooms.len() {
             let room = &self.rooms[i];
             let (new_x, new_y) = room.center();
             let (prev_x, prev_y) = self.rooms[self.rooms.len() - 1].center();
+            self.overlay
+                .corridors
+                .push(((prev_x, prev_y), (new_x, new_y)));
+            let label = format!("dig corridor {}", i + 1);
             if rng.gen_bool(0.5) {
                 self.apply_horizontal_tunnel(prev_x, new_x, prev_y);
-                self.take_snapshot();
+                self.take_snapshot(&label);
                 self.apply_vertical_tunnel(prev_y, new_y, new_x);
             } else {
                 self.apply_vertical_tunnel(prev_y, new_y, prev_x);
-                self.take_snapshot();
+                self.take_snapshot(&label);
                 self.apply_horizontal_tunnel(prev_x, new_x, new_y);
             }
-            self.take_snapshot();
+            self.take_snapshot(&label);
         }
+        self.end_phase();
+
+        place_start_in_room(&mut self.map, &self.rooms);
     }
 
     fn apply_horizontal_tunnel(&mut self, x1: i32, x2: i32, y: i32) {
         for x in min(x1, x2)..=max(x1, x2) {
             let idx = self.map.xy_idx(x, y);
             if idx > 0 && idx < (self.map.width * self.map.height) as usize {
-                self.map.tiles[idx as usize] = Some(TileType::Floor);
+                self.map.tiles[idx] = Some(TileType::Floor);
             }
         }
     }
@@ -94,7 +222,7 @@ impl RoomsMap {
         for y in min(y1, y2)..=max(y1, y2) {
             let idx = self.map.xy_idx(x, y);
             if idx > 0 && idx < (self.map.width * self.map.height) as usize {
-                self.map.tiles[idx as usize] = Some(TileType::Floor);
+                self.map.tiles[idx] = Some(TileType::Floor);
             }
         }
     }
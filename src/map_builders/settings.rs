@@ -0,0 +1,176 @@
+use crate::algorithms::Option;
+
+/// What a setting measures, shown next to its value.
+#[derive(PartialEq, Eq, Copy, Clone)]
+pub enum Unit {
+    Percent,
+    Tiles,
+    Count,
+}
+
+impl Unit {
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            Unit::Percent => "%",
+            Unit::Tiles => " tiles",
+            Unit::Count => "",
+        }
+    }
+}
+
+/// Declaration of a single setting of the settings `S`.
+pub struct Field<S> {
+    /// Stable identifier of the setting, used in presets and links.
+    pub key: &'static str,
+    pub name: &'static str,
+    pub unit: Unit,
+    pub min: i32,
+    pub max: i32,
+    pub get: fn(&S) -> i32,
+    pub set: fn(&mut S, i32),
+}
+
+/// Settings of a builder, declared once and shown in the panel from the declaration.
+pub trait Settings: Default + 'static {
+    /// Every setting, in the order they are shown.
+    const FIELDS: &'static [Field<Self>];
+
+    /// Checks settings depending on each other, ranges are checked by `errors`.
+    fn check(&self) -> Vec<String> {
+        vec![]
+    }
+
+    /// Returns every reason the settings can't be used, empty if they're valid.
+    fn errors(&self) -> Vec<String> {
+        let errors: Vec<String> = Self::FIELDS
+            .iter()
+            .filter(|field| !(field.min..=field.max).contains(&(field.get)(self)))
+            .map(|field| {
+                format!(
+                    "{} must be between {} and {}, got {}",
+                    field.name,
+                    field.min,
+                    field.max,
+                    (field.get)(self)
+                )
+            })
+            .collect();
+        if errors.is_empty() {
+            self.check()
+        } else {
+            errors
+        }
+    }
+
+    /// Settings from option values matched by key, missing values keep their default.
+    fn from_options(options: &[Option]) -> Self {
+        let mut settings = Self::default();
+        for option in options {
+            if let Some(field) = Self::FIELDS.iter().find(|field| field.key == option.key) {
+                (field.set)(&mut settings, option.value);
+            }
+        }
+        settings
+    }
+
+    /// Default values along with their declaration.
+    fn options() -> Vec<Option> {
+        let settings = Self::default();
+        Self::FIELDS
+            .iter()
+            .map(|field| Option {
+                key: field.key,
+                name: field.name,
+                value: (field.get)(&settings),
+                min: field.min,
+                max: field.max,
+                unit: field.unit,
+            })
+            .collect()
+    }
+
+    /// Returns every reason the options can't be used, empty if they're valid.
+    fn validate(options: &[Option]) -> Vec<String> {
+        Self::from_options(options).errors()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_builders::{
+        BspInteriorSettings, BspSettings, CellularAutomataSettings, DrunkardSettings,
+        RandomSettings, RoomsSettings,
+    };
+
+    /// Every field reads and writes its own value, found by key whatever the order of options.
+    fn fields_are_addressed_by_key<S: Settings>() {
+        let keys: Vec<&str> = S::FIELDS.iter().map(|field| field.key).collect();
+        for (i, key) in keys.iter().enumerate() {
+            assert!(!keys[..i].contains(key), "Duplicate key {}", key);
+        }
+
+        let mut options = S::options();
+        for (i, option) in options.iter_mut().enumerate() {
+            option.value = option.min + i as i32;
+        }
+        options.reverse();
+        let settings = S::from_options(&options);
+        for (i, field) in S::FIELDS.iter().enumerate() {
+            assert_eq!(
+                (field.get)(&settings),
+                field.min + i as i32,
+                "{}",
+                field.key
+            );
+        }
+    }
+
+    #[test]
+    fn every_builder_addresses_fields_by_key() {
+        fields_are_addressed_by_key::<RandomSettings>();
+        fields_are_addressed_by_key::<RoomsSettings>();
+        fields_are_addressed_by_key::<BspSettings>();
+        fields_are_addressed_by_key::<BspInteriorSettings>();
+        fields_are_addressed_by_key::<CellularAutomataSettings>();
+        fields_are_addressed_by_key::<DrunkardSettings>();
+    }
+
+    #[test]
+    fn unknown_and_missing_options_keep_defaults() {
+        let mut options = RoomsSettings::options();
+        options.retain(|option| option.key == "max_rooms");
+        options[0].value = 7;
+        options.push(Option {
+            key: "unknown",
+            ..options[0].clone()
+        });
+
+        let settings = RoomsSettings::from_options(&options);
+        let defaults = RoomsSettings::default();
+        assert_eq!(settings.max_rooms, 7);
+        assert_eq!(settings.min_room_size, defaults.min_room_size);
+        assert_eq!(settings.max_room_size, defaults.max_room_size);
+    }
+
+    #[test]
+    fn errors_cover_ranges_then_dependent_settings() {
+        let mut settings = RoomsSettings {
+            max_rooms: 0,
+            ..Default::default()
+        };
+        assert_eq!(
+            settings.errors(),
+            vec!["Max rooms must be between 1 and 30, got 0".to_string()]
+        );
+
+        settings.max_rooms = 5;
+        settings.min_room_size = 10;
+        settings.max_room_size = 10;
+        assert_eq!(settings.errors().len(), 1);
+        assert!(settings.errors()[0].starts_with("Min room size"));
+
+        settings.max_room_size = 12;
+        assert!(settings.errors().is_empty());
+    }
+}
@@ -0,0 +1,330 @@
+use std::{collections::VecDeque, fmt, ops::Range, str::FromStr};
+
+use crate::map::Map;
+
+use super::Rect;
+
+/// Algorithm internals recorded alongside a snapshot, drawn on top of the tiles.
+#[derive(Default, Clone)]
+pub struct Overlay {
+    /// Space partitions of the BSP builders.
+    pub partitions: Vec<Rect>,
+    /// Candidate rooms that could not be placed.
+    pub rejected_rooms: Vec<Rect>,
+    pub room_centers: Vec<(i32, i32)>,
+    /// Corridors as pairs of connected points.
+    pub corridors: Vec<((i32, i32), (i32, i32))>,
+    /// Current position of the digger, if the algorithm has one.
+    pub head: Option<(i32, i32)>,
+    /// Distance of every tile to the starting point, `f32::MAX` if unreachable.
+    pub distances: Option<Vec<f32>>,
+}
+
+#[derive(Default, Clone)]
+pub struct Snapshot {
+    pub map: Map,
+    pub overlay: Overlay,
+    /// Phase of the algorithm the snapshot was taken in, such as "place rooms".
+    pub phase: String,
+    /// What the step did, such as "place room 3".
+    pub label: String,
+    pub note: Option<String>,
+}
+
+impl Snapshot {
+    pub fn new(map: &Map, overlay: &Overlay) -> Self {
+        Self {
+            map: map.clone(),
+            overlay: overlay.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Groups consecutive snapshots of the same phase, returning every phase with the range of its
+/// snapshots.
+pub fn phases(snapshots: &[Snapshot]) -> Vec<(&str, Range<usize>)> {
+    let mut phases: Vec<(&str, Range<usize>)> = vec![];
+    for (idx, snapshot) in snapshots.iter().enumerate() {
+        match phases.last_mut() {
+            Some((phase, range)) if *phase == snapshot.phase => range.end = idx + 1,
+            _ => phases.push((&snapshot.phase, idx..idx + 1)),
+        }
+    }
+    phases
+}
+
+/// Which snapshots are recorded while a map is built.
+#[derive(Default, PartialEq, Eq, Copy, Clone)]
+pub enum SnapshotPolicy {
+    /// Nothing is recorded, building is faster and lighter.
+    Off,
+    #[default]
+    EveryStep,
+    /// One step out of this many, along with the end of every phase.
+    EveryNth(usize),
+    /// Only the map at the end of every phase, such as filling walls or placing rooms.
+    Phases,
+}
+
+impl fmt::Display for SnapshotPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotPolicy::Off => write!(f, "Off"),
+            SnapshotPolicy::EveryStep => write!(f, "Every step"),
+            SnapshotPolicy::EveryNth(_) => write!(f, "Every nth step"),
+            SnapshotPolicy::Phases => write!(f, "End of phases"),
+        }
+    }
+}
+
+impl FromStr for SnapshotPolicy {
+    type Err = String;
+
+    /// Parses `off`, `all`, `phases` or `every:<n>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "off" => Ok(SnapshotPolicy::Off),
+            None if s == "all" => Ok(SnapshotPolicy::EveryStep),
+            None if s == "phases" => Ok(SnapshotPolicy::Phases),
+            Some(("every", n)) => match n.parse() {
+                Ok(n) if n > 0 => Ok(SnapshotPolicy::EveryNth(n)),
+                _ => Err(format!("Invalid step count '{}'", n)),
+            },
+            _ => Err(format!("Unknown snapshot policy '{}'", s)),
+        }
+    }
+}
+
+impl SnapshotPolicy {
+    pub fn all() -> Vec<Self> {
+        vec![
+            SnapshotPolicy::Off,
+            SnapshotPolicy::EveryStep,
+            SnapshotPolicy::EveryNth(10),
+            SnapshotPolicy::Phases,
+        ]
+    }
+}
+
+/// Snapshots taken while a map is built, following a policy.
+#[derive(Default)]
+pub struct SnapshotHistory {
+    snapshots: VecDeque<Snapshot>,
+    policy: SnapshotPolicy,
+    /// Steps taken so far, recorded or not.
+    steps: usize,
+    phase: String,
+    /// Label and note of the last step.
+    label: String,
+    note: Option<String>,
+    /// Note attached to the next step.
+    next_note: Option<String>,
+    /// Whether the last step was skipped by the policy.
+    pending: bool,
+}
+
+impl SnapshotHistory {
+    pub fn set_policy(&mut self, policy: SnapshotPolicy) {
+        self.policy = policy;
+    }
+
+    /// Forgets the snapshots and steps of the last build, keeping the policy.
+    pub fn clear(&mut self) {
+        *self = Self {
+            policy: self.policy,
+            ..Default::default()
+        };
+    }
+
+    /// Ends the current phase and names the next one.
+    pub fn start_phase(&mut self, map: &Map, overlay: &Overlay, phase: &str) {
+        self.end_phase(map, overlay);
+        self.phase = phase.to_string();
+    }
+
+    /// Attaches a note to the next step.
+    pub fn add_note(&mut self, note: &str) {
+        self.next_note = Some(note.to_string());
+    }
+
+    /// Records a step of the algorithm if the policy keeps it.
+    pub fn push(&mut self, map: &Map, overlay: &Overlay, label: &str) {
+        let recorded = match self.policy {
+            SnapshotPolicy::EveryStep => true,
+            SnapshotPolicy::EveryNth(n) => self.steps.is_multiple_of(n.max(1)),
+            SnapshotPolicy::Off | SnapshotPolicy::Phases => false,
+        };
+        self.steps += 1;
+        self.label = label.to_string();
+        self.note = self.next_note.take();
+        self.pending = !recorded;
+        if recorded {
+            self.record(map, overlay);
+        }
+    }
+
+    /// Marks the last step as the end of a phase, recording it unless it already was or
+    /// snapshots are off.
+    pub fn end_phase(&mut self, map: &Map, overlay: &Overlay) {
+        if self.policy != SnapshotPolicy::Off && self.pending {
+            self.record(map, overlay);
+            self.pending = false;
+        }
+    }
+
+    fn record(&mut self, map: &Map, overlay: &Overlay) {
+        self.snapshots.push_back(Snapshot {
+            phase: self.phase.clone(),
+            label: self.label.clone(),
+            note: self.note.clone(),
+            ..Snapshot::new(map, overlay)
+        });
+    }
+
+    pub fn history(&self) -> VecDeque<Snapshot> {
+        self.snapshots.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// History of two phases, "a" with five steps and "b" with one, the second step of "a"
+    /// carrying a note.
+    fn history(policy: SnapshotPolicy) -> Vec<Snapshot> {
+        let (map, overlay) = (Map::new(3, 3), Overlay::default());
+        let mut history = SnapshotHistory::default();
+        history.set_policy(policy);
+        history.start_phase(&map, &overlay, "a");
+        for step in 1..=5 {
+            if step == 2 {
+                history.add_note("second");
+            }
+            history.push(&map, &overlay, &step.to_string());
+        }
+        history.start_phase(&map, &overlay, "b");
+        history.push(&map, &overlay, "6");
+        history.end_phase(&map, &overlay);
+        history.history().into()
+    }
+
+    fn labels(snapshots: &[Snapshot]) -> Vec<(&str, &str)> {
+        snapshots
+            .iter()
+            .map(|snapshot| (snapshot.phase.as_str(), snapshot.label.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn every_step_is_recorded_once() {
+        let snapshots = history(SnapshotPolicy::EveryStep);
+        let expected: Vec<(&str, &str)> = vec![
+            ("a", "1"),
+            ("a", "2"),
+            ("a", "3"),
+            ("a", "4"),
+            ("a", "5"),
+            ("b", "6"),
+        ];
+        assert_eq!(labels(&snapshots), expected);
+        assert!(history(SnapshotPolicy::Off).is_empty());
+    }
+
+    #[test]
+    fn every_nth_step_is_recorded_along_with_the_end_of_phases() {
+        let snapshots = history(SnapshotPolicy::EveryNth(3));
+        assert_eq!(
+            labels(&snapshots),
+            vec![("a", "1"), ("a", "4"), ("a", "5"), ("b", "6")]
+        );
+        // The last step of a phase isn't recorded twice
+        let snapshots = history(SnapshotPolicy::EveryNth(5));
+        assert_eq!(labels(&snapshots), vec![("a", "1"), ("a", "5"), ("b", "6")]);
+    }
+
+    #[test]
+    fn phases_record_their_last_step() {
+        let snapshots = history(SnapshotPolicy::Phases);
+        assert_eq!(labels(&snapshots), vec![("a", "5"), ("b", "6")]);
+    }
+
+    #[test]
+    fn notes_stay_on_their_step() {
+        let snapshots = history(SnapshotPolicy::EveryStep);
+        let notes: Vec<Option<&str>> = snapshots.iter().map(|s| s.note.as_deref()).collect();
+        assert_eq!(notes, vec![None, Some("second"), None, None, None, None]);
+        // A skipped step doesn't pass its note to the step recorded at the end of the phase
+        let snapshots = history(SnapshotPolicy::Phases);
+        assert!(snapshots.iter().all(|snapshot| snapshot.note.is_none()));
+        let snapshots = history(SnapshotPolicy::EveryNth(2));
+        assert_eq!(labels(&snapshots)[1], ("a", "3"));
+        assert!(snapshots.iter().all(|snapshot| snapshot.note.is_none()));
+    }
+
+    #[test]
+    fn end_of_phase_keeps_the_note_of_its_step() {
+        let (map, overlay) = (Map::new(3, 3), Overlay::default());
+        let mut history = SnapshotHistory::default();
+        history.set_policy(SnapshotPolicy::Phases);
+        history.start_phase(&map, &overlay, "dig");
+        history.add_note("dead end");
+        history.push(&map, &overlay, "dig 1");
+        history.end_phase(&map, &overlay);
+        history.end_phase(&map, &overlay);
+        let snapshots = history.history();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].note.as_deref(), Some("dead end"));
+    }
+
+    #[test]
+    fn clearing_keeps_the_policy_and_restarts_the_steps() {
+        let (map, overlay) = (Map::new(3, 3), Overlay::default());
+        let mut history = SnapshotHistory::default();
+        history.set_policy(SnapshotPolicy::EveryNth(2));
+        history.start_phase(&map, &overlay, "a");
+        history.push(&map, &overlay, "1");
+        history.push(&map, &overlay, "2");
+
+        history.clear();
+        assert!(history.history().is_empty());
+        history.start_phase(&map, &overlay, "b");
+        history.push(&map, &overlay, "1");
+        history.push(&map, &overlay, "2");
+        history.push(&map, &overlay, "3");
+        assert_eq!(
+            labels(&Vec::from(history.history())),
+            vec![("b", "1"), ("b", "3")]
+        );
+    }
+
+    #[test]
+    fn phases_group_consecutive_snapshots() {
+        let snapshot = |phase: &str| Snapshot {
+            phase: phase.to_string(),
+            ..Default::default()
+        };
+        let snapshots = [snapshot("a"), snapshot("a"), snapshot("b"), snapshot("a")];
+        assert_eq!(
+            phases(&snapshots),
+            vec![("a", 0..2), ("b", 2..3), ("a", 3..4)]
+        );
+        assert!(phases(&[]).is_empty());
+        assert_eq!(phases(&snapshots[2..3]), vec![("b", 0..1)]);
+    }
+
+    #[test]
+    fn snapshot_policies_parse() {
+        let parse = |s: &str| s.parse::<SnapshotPolicy>();
+        assert!(parse("off") == Ok(SnapshotPolicy::Off));
+        assert!(parse("all") == Ok(SnapshotPolicy::EveryStep));
+        assert!(parse("phases") == Ok(SnapshotPolicy::Phases));
+        assert!(parse("every:1") == Ok(SnapshotPolicy::EveryNth(1)));
+        assert!(parse("every:25") == Ok(SnapshotPolicy::EveryNth(25)));
+        assert!(parse("every:0") == Err("Invalid step count '0'".to_string()));
+        assert!(parse("every:x") == Err("Invalid step count 'x'".to_string()));
+        assert!(parse("every") == Err("Unknown snapshot policy 'every'".to_string()));
+        assert!(parse("phases:2").is_err());
+    }
+}
@@ -0,0 +1,111 @@
+use serde::Serialize;
+
+use crate::{
+    analysis::{find_path, regions, DIAGONAL, ORTHOGONAL},
+    map::Map,
+    map_builders::Rect,
+    room_graph::RoomGraph,
+};
+
+/// Numbers describing the layout of a generated map.
+#[derive(Default, Clone, Serialize)]
+pub struct MapMetrics {
+    /// Share of the map that is floor.
+    pub floor_ratio: f32,
+    /// Number of floor regions, tiles touching diagonally are connected.
+    pub regions: usize,
+    /// Tiles in the biggest connected region.
+    pub largest_region: usize,
+    /// Floor tiles with a single floor neighbor.
+    pub dead_ends: usize,
+    /// Share of floor tiles that are one tile wide corridors instead of rooms.
+    pub corridor_ratio: f32,
+    /// Average room area, only known for builders placing rooms.
+    pub average_room_size: Option<f32>,
+    /// Rooms placed by the builder, or found in the tiles for maps without any.
+    pub rooms: usize,
+    /// Rooms joined to a single other room.
+    pub dead_end_rooms: usize,
+    /// Steps from start to exit, `None` if there's no start, exit or path.
+    pub path_length: Option<usize>,
+    /// Independent cycles in the walkable area.
+    pub loops: usize,
+}
+
+impl MapMetrics {
+    pub fn new(map: &Map, rooms: Option<&[Rect]>) -> Self {
+        let floor: Vec<bool> = (0..map.tiles.len())
+            .map(|idx| map.is_walkable(idx))
+            .collect();
+        let floor_tiles = floor.iter().filter(|f| **f).count();
+        let region_sizes: Vec<usize> = regions(map, true).iter().map(Vec::len).collect();
+
+        let mut dead_ends = 0;
+        let mut corridors = 0;
+        let mut edges = 0;
+        let mut squares = 0;
+        for (idx, _) in floor.iter().enumerate().filter(|(_, f)| **f) {
+            let (x, y) = map.idx_xy(idx);
+            let is_floor = |dx: i32, dy: i32| {
+                let (nx, ny) = (x + dx, y + dy);
+                nx >= 0 && nx < map.width && ny >= 0 && ny < map.height && floor[map.xy_idx(nx, ny)]
+            };
+
+            let neighbors = ORTHOGONAL
+                .iter()
+                .filter(|(dx, dy)| is_floor(*dx, *dy))
+                .count();
+            if neighbors == 1 {
+                dead_ends += 1;
+            }
+
+            // Tiles that aren't part of any 2x2 block of floor are corridors
+            let in_block = DIAGONAL
+                .iter()
+                .any(|(dx, dy)| is_floor(*dx, 0) && is_floor(0, *dy) && is_floor(*dx, *dy));
+            if !in_block {
+                corridors += 1;
+            }
+
+            // Count every edge and block once, from its top left tile
+            edges += is_floor(1, 0) as usize + is_floor(0, 1) as usize;
+            if is_floor(1, 0) && is_floor(0, 1) && is_floor(1, 1) {
+                squares += 1;
+            }
+        }
+
+        // The cycle rank of the orthogonal grid graph counts every 2x2 block of floor as a cycle,
+        // only holes around walls are loops a player can walk.
+        let components = regions(map, false).len();
+        let loops = (edges + components).saturating_sub(floor_tiles + squares);
+
+        let graph = RoomGraph::new(map, rooms);
+
+        let path_length = match (map.start, map.exit) {
+            (Some(start), Some(exit)) => {
+                let path = find_path(map, start, exit);
+                (!path.is_empty()).then(|| path.len() - 1)
+            }
+            _ => None,
+        };
+
+        Self {
+            floor_ratio: floor_tiles as f32 / map.tiles.len().max(1) as f32,
+            regions: region_sizes.len(),
+            largest_region: region_sizes.iter().copied().max().unwrap_or(0),
+            dead_ends,
+            corridor_ratio: corridors as f32 / floor_tiles.max(1) as f32,
+            average_room_size: rooms.filter(|r| !r.is_empty()).map(|rooms| {
+                rooms
+                    .iter()
+                    .map(|r| ((r.x2 - r.x1) * (r.y2 - r.y1)) as f32)
+                    .sum::<f32>()
+                    / rooms.len() as f32
+            }),
+            rooms: graph.nodes.len(),
+            dead_end_rooms: graph.leaves().len(),
+            path_length,
+            loops,
+        }
+    }
+}
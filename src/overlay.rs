@@ -0,0 +1,407 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use bevy_egui::EguiContext;
+use iyes_loopless::prelude::*;
+use procedural_map::{
+    analysis::Analysis,
+    fov::field_of_view,
+    generator::Constraint,
+    map::{Map, TILE_SIZE},
+    map_builders::{Rect, Snapshot},
+    metrics::MapMetrics,
+};
+
+use crate::{ComparisonMap, MapComponent, States};
+
+const LINE_WIDTH: f32 = 2.;
+
+#[derive(Component)]
+pub struct OverlayComponent;
+
+#[derive(Component)]
+pub struct AnalysisComponent;
+
+#[derive(Component)]
+pub struct FovComponent;
+
+/// Field of view from the last clicked tile of the generated map.
+pub struct FovPreview {
+    origin: usize,
+    visible: HashSet<usize>,
+}
+
+/// Snapshot currently on screen, its overlay gets drawn over the tiles.
+pub struct LastSnapshot(pub Snapshot);
+
+/// Analysis of the generated map, shown once the playback is over.
+pub struct MapAnalysis {
+    pub map: Map,
+    pub analysis: Option<Analysis>,
+    pub metrics: MapMetrics,
+    /// Fitness of the kept map and of every generated candidate.
+    pub score: f32,
+    pub scores: Vec<f32>,
+    pub seed: u64,
+    /// Whether the map meets every constraint and how many attempts it took.
+    pub satisfied: bool,
+    pub attempts: usize,
+    pub failures: Vec<(Constraint, usize)>,
+    pub most_failed: Option<(Constraint, usize)>,
+}
+
+/// Which overlays are drawn.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct OverlaySettings {
+    pub partitions: bool,
+    pub rejected_rooms: bool,
+    pub room_centers: bool,
+    pub corridors: bool,
+    pub head: bool,
+    pub distances: bool,
+    pub areas: bool,
+    pub analysis: bool,
+    /// Whether clicking a tile shows its field of view, and how far it goes.
+    pub fov: bool,
+    pub fov_radius: i32,
+}
+
+impl Default for OverlaySettings {
+    fn default() -> Self {
+        Self {
+            partitions: false,
+            rejected_rooms: false,
+            room_centers: false,
+            corridors: false,
+            head: false,
+            distances: false,
+            areas: false,
+            analysis: false,
+            fov: false,
+            fov_radius: 8,
+        }
+    }
+}
+
+pub fn draw_overlay(
+    mut cmds: Commands,
+    settings: Res<OverlaySettings>,
+    snapshot: Option<Res<LastSnapshot>>,
+    parent: Query<Entity, (With<MapComponent>, Without<ComparisonMap>)>,
+    overlays: Query<Entity, With<OverlayComponent>>,
+) {
+    let snapshot = match snapshot {
+        Some(snapshot) => snapshot,
+        None => return,
+    };
+    if !settings.is_changed() && !snapshot.is_changed() {
+        return;
+    }
+    let parent = match parent.get_single() {
+        Ok(parent) => parent,
+        Err(_) => return,
+    };
+
+    for e in overlays.iter() {
+        cmds.entity(e).despawn_recursive();
+    }
+
+    let map = &snapshot.0.map;
+    let overlay = &snapshot.0.overlay;
+    let mut sprites = vec![];
+
+    if settings.distances {
+        if let Some(distances) = &overlay.distances {
+            sprites.extend(heatmap(map, distances));
+        }
+    }
+    if settings.areas {
+        sprites.extend(area_colors(map));
+    }
+    if settings.partitions {
+        for rect in overlay.partitions.iter() {
+            sprites.extend(rect_outline(map, rect, Color::rgba(0.2, 0.6, 1., 0.8)));
+        }
+    }
+    if settings.rejected_rooms {
+        for rect in overlay.rejected_rooms.iter() {
+            sprites.extend(rect_outline(map, rect, Color::rgba(1., 0.2, 0.2, 0.6)));
+        }
+    }
+    if settings.corridors {
+        for (from, to) in overlay.corridors.iter() {
+            sprites.push(line(
+                map.tile_position(from.0, from.1),
+                map.tile_position(to.0, to.1),
+                Color::YELLOW,
+            ));
+        }
+    }
+    if settings.room_centers {
+        for (x, y) in overlay.room_centers.iter() {
+            sprites.push(marker(map.tile_position(*x, *y), Color::GREEN));
+        }
+    }
+    if settings.head {
+        if let Some((x, y)) = overlay.head {
+            sprites.push(marker(map.tile_position(x, y), Color::FUCHSIA));
+        }
+    }
+
+    for sprite in sprites {
+        let entity = cmds.spawn_bundle(sprite).insert(OverlayComponent).id();
+        cmds.entity(parent).add_child(entity);
+    }
+}
+
+pub fn draw_analysis(
+    mut cmds: Commands,
+    settings: Res<OverlaySettings>,
+    analysis: Option<Res<MapAnalysis>>,
+    state: Res<CurrentState<States>>,
+    parent: Query<Entity, (With<MapComponent>, Without<ComparisonMap>)>,
+    drawn: Query<Entity, With<AnalysisComponent>>,
+) {
+    let analysis = match analysis {
+        Some(analysis) => analysis,
+        None => return,
+    };
+    if !settings.is_changed() && !analysis.is_changed() && !state.is_changed() {
+        return;
+    }
+
+    for e in drawn.iter() {
+        cmds.entity(e).despawn_recursive();
+    }
+
+    if !settings.analysis || state.0 != States::Menu {
+        return;
+    }
+    let parent = match parent.get_single() {
+        Ok(parent) => parent,
+        Err(_) => return,
+    };
+    let map = &analysis.map;
+    let result = match &analysis.analysis {
+        Some(result) => result,
+        None => return,
+    };
+
+    let mut sprites = heatmap(map, &result.distances);
+    for step in result.path.windows(2) {
+        let (x1, y1) = map.idx_xy(step[0]);
+        let (x2, y2) = map.idx_xy(step[1]);
+        sprites.push(line(
+            map.tile_position(x1, y1),
+            map.tile_position(x2, y2),
+            Color::WHITE,
+        ));
+    }
+    if let Some(start) = map.start {
+        let (x, y) = map.idx_xy(start);
+        sprites.push(marker(map.tile_position(x, y), Color::GREEN));
+    }
+    if let Some(exit) = map.exit {
+        let (x, y) = map.idx_xy(exit);
+        sprites.push(marker(map.tile_position(x, y), Color::RED));
+    }
+
+    for sprite in sprites {
+        let entity = cmds.spawn_bundle(sprite).insert(AnalysisComponent).id();
+        cmds.entity(parent).add_child(entity);
+    }
+}
+
+/// Computes the field of view of the clicked tile of the generated map.
+pub fn pick_fov_origin(
+    mut cmds: Commands,
+    settings: Res<OverlaySettings>,
+    analysis: Option<Res<MapAnalysis>>,
+    buttons: Res<Input<MouseButton>>,
+    windows: Res<Windows>,
+    mut egui_ctx: ResMut<EguiContext>,
+    parent: Query<&Transform, (With<MapComponent>, Without<ComparisonMap>)>,
+) {
+    if !settings.fov
+        || !buttons.just_pressed(MouseButton::Left)
+        || egui_ctx.ctx_mut().is_pointer_over_area()
+    {
+        return;
+    }
+    let (analysis, transform) = match (analysis, parent.get_single()) {
+        (Some(analysis), Ok(transform)) => (analysis, transform),
+        _ => return,
+    };
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let cursor = match window.cursor_position() {
+        Some(cursor) => cursor,
+        None => return,
+    };
+
+    // The camera never moves, the center of the window is the origin of the world
+    let world = cursor - Vec2::new(window.width(), window.height()) / 2.;
+    let local = (world - transform.translation.truncate()) / transform.scale.truncate();
+    let map = &analysis.map;
+    let x = (local.x / TILE_SIZE as f32).round() as i32 + map.width / 2;
+    let y = (local.y / TILE_SIZE as f32).round() as i32 + map.height / 2;
+    if x < 0 || x >= map.width || y < 0 || y >= map.height {
+        return;
+    }
+
+    let origin = map.xy_idx(x, y);
+    cmds.insert_resource(FovPreview {
+        origin,
+        visible: field_of_view(map, origin, settings.fov_radius),
+    });
+}
+
+/// Darkens the tiles out of sight of the clicked tile once the playback is over.
+pub fn draw_fov(
+    mut cmds: Commands,
+    settings: Res<OverlaySettings>,
+    preview: Option<Res<FovPreview>>,
+    analysis: Option<Res<MapAnalysis>>,
+    state: Res<CurrentState<States>>,
+    parent: Query<Entity, (With<MapComponent>, Without<ComparisonMap>)>,
+    drawn: Query<Entity, With<FovComponent>>,
+) {
+    let changed = preview.as_ref().is_some_and(|preview| preview.is_changed())
+        || analysis
+            .as_ref()
+            .is_some_and(|analysis| analysis.is_changed());
+    if !settings.is_changed() && !changed && !state.is_changed() {
+        return;
+    }
+
+    for e in drawn.iter() {
+        cmds.entity(e).despawn_recursive();
+    }
+
+    if !settings.fov || state.0 != States::Menu {
+        return;
+    }
+    let (preview, analysis, parent) = match (preview, analysis, parent.get_single()) {
+        (Some(preview), Some(analysis), Ok(parent)) => (preview, analysis, parent),
+        _ => return,
+    };
+    let map = &analysis.map;
+
+    let mut sprites: Vec<SpriteBundle> = (0..map.tiles.len())
+        .filter(|idx| !preview.visible.contains(idx))
+        .map(|idx| {
+            let (x, y) = map.idx_xy(idx);
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba(0., 0., 0., 0.7),
+                    custom_size: Some(Vec2::splat(TILE_SIZE as f32)),
+                    ..default()
+                },
+                transform: Transform::from_translation(map.tile_position(x, y).extend(1.)),
+                ..default()
+            }
+        })
+        .collect();
+    let (x, y) = map.idx_xy(preview.origin);
+    sprites.push(marker(map.tile_position(x, y), Color::YELLOW));
+
+    for sprite in sprites {
+        let entity = cmds.spawn_bundle(sprite).insert(FovComponent).id();
+        cmds.entity(parent).add_child(entity);
+    }
+}
+
+/// Colors every reachable tile from blue (close to the start) to red (far away).
+pub fn heatmap(map: &Map, distances: &[f32]) -> Vec<SpriteBundle> {
+    let max = distances
+        .iter()
+        .copied()
+        .filter(|d| *d < f32::MAX)
+        .fold(0., f32::max);
+    if max <= 0. {
+        return vec![];
+    }
+
+    distances
+        .iter()
+        .enumerate()
+        .filter(|(_, d)| **d < f32::MAX)
+        .map(|(idx, d)| {
+            let (x, y) = map.idx_xy(idx);
+            let t = d / max;
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba(t, 0., 1. - t, 0.5),
+                    custom_size: Some(Vec2::splat(TILE_SIZE as f32)),
+                    ..default()
+                },
+                transform: Transform::from_translation(map.tile_position(x, y).extend(1.)),
+                ..default()
+            }
+        })
+        .collect()
+}
+
+/// Gives every area of the map its own color, hues are spread by the golden angle so
+/// neighboring ids stand apart.
+fn area_colors(map: &Map) -> Vec<SpriteBundle> {
+    map.areas
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, id)| id.map(|id| (idx, id)))
+        .map(|(idx, id)| {
+            let (x, y) = map.idx_xy(idx);
+            let hue = (id as f32 * 137.5) % 360.;
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::hsla(hue, 0.8, 0.5, 0.5),
+                    custom_size: Some(Vec2::splat(TILE_SIZE as f32)),
+                    ..default()
+                },
+                transform: Transform::from_translation(map.tile_position(x, y).extend(1.)),
+                ..default()
+            }
+        })
+        .collect()
+}
+
+fn rect_outline(map: &Map, rect: &Rect, color: Color) -> Vec<SpriteBundle> {
+    let half = TILE_SIZE as f32 / 2.;
+    let min = map.tile_position(rect.x1, rect.y1) - half;
+    let max = map.tile_position(rect.x2, rect.y2) + half;
+
+    vec![
+        line(min, Vec2::new(max.x, min.y), color),
+        line(Vec2::new(max.x, min.y), max, color),
+        line(max, Vec2::new(min.x, max.y), color),
+        line(Vec2::new(min.x, max.y), min, color),
+    ]
+}
+
+pub fn line(from: Vec2, to: Vec2, color: Color) -> SpriteBundle {
+    let delta = to - from;
+    SpriteBundle {
+        sprite: Sprite {
+            color,
+            custom_size: Some(Vec2::new(delta.length() + LINE_WIDTH, LINE_WIDTH)),
+            ..default()
+        },
+        transform: Transform::from_translation(((from + to) / 2.).extend(2.))
+            .with_rotation(Quat::from_rotation_z(delta.y.atan2(delta.x))),
+        ..default()
+    }
+}
+
+pub fn marker(position: Vec2, color: Color) -> SpriteBundle {
+    SpriteBundle {
+        sprite: Sprite {
+            color,
+            custom_size: Some(Vec2::splat(TILE_SIZE as f32 / 2.)),
+            ..default()
+        },
+        transform: Transform::from_translation(position.extend(3.)),
+        ..default()
+    }
+}
@@ -0,0 +1,357 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use bevy_egui::EguiContext;
+use bracket_pathfinding::prelude::BaseMap;
+use iyes_loopless::prelude::*;
+use procedural_map::{
+    fov::field_of_view,
+    map::{Map, TextureMap, TileType, TILE_SIZE},
+};
+
+use crate::{overlay::MapAnalysis, ComparisonMap, MapComponent, States};
+
+/// How far the player sees, in tiles.
+const SIGHT_RADIUS: i32 = 8;
+/// Sprite of the player in the atlas, `@`.
+const PLAYER_SPRITE: usize = 64;
+
+/// Keys moving the player and their direction, diagonals use the corners of WASD.
+const MOVES: [(KeyCode, (i32, i32)); 12] = [
+    (KeyCode::Up, (0, 1)),
+    (KeyCode::W, (0, 1)),
+    (KeyCode::Down, (0, -1)),
+    (KeyCode::S, (0, -1)),
+    (KeyCode::Left, (-1, 0)),
+    (KeyCode::A, (-1, 0)),
+    (KeyCode::Right, (1, 0)),
+    (KeyCode::D, (1, 0)),
+    (KeyCode::Q, (-1, 1)),
+    (KeyCode::E, (1, 1)),
+    (KeyCode::Z, (-1, -1)),
+    (KeyCode::C, (1, -1)),
+];
+
+#[derive(Component)]
+pub struct PlayComponent;
+
+/// Player walking the generated map and the tiles they have seen.
+pub struct Player {
+    /// The generated map with the doors the player opened.
+    map: Map,
+    position: usize,
+    visible: HashSet<usize>,
+    explored: Vec<bool>,
+    /// Doors opened so far, in order.
+    opened: Vec<usize>,
+    /// Keys picked up so far, by their tile.
+    keys: HashSet<usize>,
+    pub moves: usize,
+}
+
+impl Player {
+    fn new(map: Map, position: usize) -> Self {
+        let mut player = Self {
+            position,
+            visible: HashSet::new(),
+            explored: vec![false; map.tiles.len()],
+            opened: vec![],
+            keys: HashSet::new(),
+            moves: 0,
+            map,
+        };
+        player.look();
+        player
+    }
+
+    /// Tile a step in a direction leads to, `None` if the exits of the map don't allow it or if
+    /// it is a locked door whose key the player hasn't picked up.
+    fn target(&self, (dx, dy): (i32, i32)) -> Option<usize> {
+        let (x, y) = self.map.idx_xy(self.position);
+        let (x, y) = (x + dx, y + dy);
+        if x < 0 || x >= self.map.width || y < 0 || y >= self.map.height {
+            return None;
+        }
+        let target = self.map.xy_idx(x, y);
+        let locked = self
+            .map
+            .locks
+            .iter()
+            .any(|lock| lock.door == target && !self.keys.contains(&lock.key));
+        let exits = self.map.get_available_exits(self.position);
+        (!locked && exits.iter().any(|(idx, _)| *idx == target)).then_some(target)
+    }
+
+    /// Moves by a tile following the exits of the map, opens the closed door walked into and
+    /// picks up the key stepped on. Returns whether the player moved.
+    fn step(&mut self, direction: (i32, i32)) -> bool {
+        let target = match self.target(direction) {
+            Some(target) => target,
+            None => return false,
+        };
+
+        if self.map.tiles[target] == Some(TileType::Door { open: false }) {
+            self.map.tiles[target] = Some(TileType::Door { open: true });
+            self.opened.push(target);
+        }
+        if self.map.locks.iter().any(|lock| lock.key == target) {
+            self.keys.insert(target);
+        }
+        self.position = target;
+        self.moves += 1;
+        self.look();
+        true
+    }
+
+    fn look(&mut self) {
+        self.visible = field_of_view(&self.map, self.position, SIGHT_RADIUS);
+        for idx in self.visible.iter() {
+            self.explored[*idx] = true;
+        }
+    }
+
+    /// Number of keys picked up so far.
+    pub fn keys(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Share of the map seen so far.
+    pub fn explored_ratio(&self) -> f32 {
+        let explored = self.explored.iter().filter(|e| **e).count();
+        explored as f32 / self.explored.len().max(1) as f32
+    }
+}
+
+/// Drops the player on the start of the generated map, or goes back to the menu without one.
+pub fn enter_play(mut cmds: Commands, analysis: Option<Res<MapAnalysis>>) {
+    let player = analysis.and_then(|analysis| {
+        let start = analysis.map.start?;
+        Some(Player::new(analysis.map.clone(), start))
+    });
+    match player {
+        Some(player) => cmds.insert_resource(player),
+        None => cmds.insert_resource(NextState(States::Menu)),
+    }
+}
+
+pub fn leave_play(mut cmds: Commands, drawn: Query<Entity, With<PlayComponent>>) {
+    for e in drawn.iter() {
+        cmds.entity(e).despawn_recursive();
+    }
+    cmds.remove_resource::<Player>();
+}
+
+/// Moves the player by a tile following the exits of the map, Escape goes back to the menu.
+pub fn move_player(
+    mut cmds: Commands,
+    keys: Res<Input<KeyCode>>,
+    mut egui_ctx: ResMut<EguiContext>,
+    player: Option<ResMut<Player>>,
+) {
+    // Keys typed in the panel don't move the player
+    if egui_ctx.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+    if keys.just_pressed(KeyCode::Escape) {
+        cmds.insert_resource(NextState(States::Menu));
+        return;
+    }
+    let mut player = match player {
+        Some(player) => player,
+        None => return,
+    };
+    let direction = match MOVES.iter().find(|(key, _)| keys.just_pressed(*key)) {
+        Some((_, direction)) => *direction,
+        None => return,
+    };
+
+    // The player is only redrawn when they moved
+    if player.target(direction).is_some() {
+        player.step(direction);
+    }
+}
+
+/// Draws the opened doors over the map, hides the tiles never seen, dims the ones out of sight
+/// and draws the player.
+pub fn draw_player(
+    mut cmds: Commands,
+    player: Option<Res<Player>>,
+    texture: Res<TextureMap>,
+    parent: Query<Entity, (With<MapComponent>, Without<ComparisonMap>)>,
+    drawn: Query<Entity, With<PlayComponent>>,
+) {
+    let (player, parent) = match (player, parent.get_single()) {
+        (Some(player), Ok(parent)) => (player, parent),
+        _ => return,
+    };
+    if !player.is_changed() {
+        return;
+    }
+
+    for e in drawn.iter() {
+        cmds.entity(e).despawn_recursive();
+    }
+
+    let map = &player.map;
+    let mut entities = vec![];
+    for idx in player.opened.iter().copied() {
+        let (x, y) = map.idx_xy(idx);
+        let position = map.tile_position(x, y);
+        let background = SpriteBundle {
+            sprite: Sprite {
+                color: Color::BLACK,
+                custom_size: Some(Vec2::splat(TILE_SIZE as f32)),
+                ..default()
+            },
+            transform: Transform::from_translation(position.extend(0.4)),
+            ..default()
+        };
+        entities.push(cmds.spawn_bundle(background).insert(PlayComponent).id());
+        if let Some(index) = map.sprite(idx) {
+            let mut sprite = TextureAtlasSprite::new(index);
+            sprite.custom_size = Some(Vec2::splat(TILE_SIZE as f32));
+            let door = SpriteSheetBundle {
+                texture_atlas: texture.0.clone(),
+                sprite,
+                transform: Transform::from_translation(position.extend(0.5)),
+                ..default()
+            };
+            entities.push(cmds.spawn_bundle(door).insert(PlayComponent).id());
+        }
+    }
+    for idx in (0..map.tiles.len()).filter(|idx| !player.visible.contains(idx)) {
+        let (x, y) = map.idx_xy(idx);
+        let alpha = if player.explored[idx] { 0.6 } else { 1. };
+        let fog = SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgba(0., 0., 0., alpha),
+                custom_size: Some(Vec2::splat(TILE_SIZE as f32)),
+                ..default()
+            },
+            transform: Transform::from_translation(map.tile_position(x, y).extend(1.)),
+            ..default()
+        };
+        entities.push(cmds.spawn_bundle(fog).insert(PlayComponent).id());
+    }
+
+    let (x, y) = map.idx_xy(player.position);
+    let mut sprite = TextureAtlasSprite::new(PLAYER_SPRITE);
+    sprite.custom_size = Some(Vec2::splat(TILE_SIZE as f32));
+    sprite.color = Color::YELLOW;
+    let bundle = SpriteSheetBundle {
+        texture_atlas: texture.0.clone(),
+        sprite,
+        transform: Transform::from_translation(map.tile_position(x, y).extend(4.)),
+        ..default()
+    };
+    entities.push(cmds.spawn_bundle(bundle).insert(PlayComponent).id());
+
+    cmds.entity(parent).push_children(&entities);
+}
+
+#[cfg(test)]
+mod tests {
+    use procedural_map::puzzle::Lock;
+
+    use super::*;
+
+    fn player_at(rows: &[&str], x: i32, y: i32) -> Player {
+        let map = Map::from_rows(rows);
+        let position = map.xy_idx(x, y);
+        Player::new(map, position)
+    }
+
+    #[test]
+    fn walls_and_the_edges_block_moves() {
+        let mut player = player_at(&["#####", "#...#", "#####"], 1, 1);
+        for direction in [(-1, 0), (0, 1), (0, -1), (-1, -1), (1, 1)] {
+            assert!(!player.step(direction));
+        }
+        assert_eq!((player.position, player.moves), (6, 0));
+
+        let mut player = player_at(&["..."], 0, 0);
+        assert!(player.target((-1, 0)).is_none());
+        assert!(!player.step((0, -1)));
+        assert_eq!(player.moves, 0);
+    }
+
+    #[test]
+    fn moves_follow_the_exits_of_the_map() {
+        let mut player = player_at(&["#####", "#...#", "#...#", "#####"], 1, 1);
+        assert!(player.step((1, 1)));
+        assert!(player.step((1, 0)));
+        assert!(player.step((0, -1)));
+        assert_eq!(player.map.idx_xy(player.position), (3, 1));
+        assert_eq!(player.moves, 3);
+    }
+
+    #[test]
+    fn walking_into_a_closed_door_opens_it() {
+        let mut player = player_at(&["############", "#....+.....#", "############"], 4, 1);
+        let door = player.map.xy_idx(5, 1);
+        let behind = player.map.xy_idx(6, 1);
+        assert!(player.visible.contains(&door));
+        assert!(!player.visible.contains(&behind));
+
+        assert!(player.step((1, 0)));
+        assert_eq!(player.position, door);
+        assert_eq!(player.map.tiles[door], Some(TileType::Door { open: true }));
+        assert_eq!(player.opened, vec![door]);
+        assert!(player.visible.contains(&player.map.xy_idx(10, 1)));
+
+        // Doors already open stay in the list once
+        assert!(player.step((1, 0)));
+        assert!(player.step((-1, 0)));
+        assert_eq!(player.opened, vec![door]);
+    }
+
+    #[test]
+    fn locked_doors_only_open_with_their_key() {
+        let mut player = player_at(&["#########", "#...+...#", "#########"], 3, 1);
+        let door = player.map.xy_idx(4, 1);
+        let key = player.map.xy_idx(1, 1);
+        player.map.locks.push(Lock { door, key });
+
+        assert!(player.target((1, 0)).is_none());
+        assert!(!player.step((1, 0)));
+        assert_eq!(player.map.tiles[door], Some(TileType::Door { open: false }));
+        assert_eq!((player.keys(), player.moves), (0, 0));
+
+        assert!(player.step((-1, 0)));
+        assert!(player.step((-1, 0)));
+        assert_eq!(player.keys(), 1);
+        assert!(player.step((1, 0)));
+        assert!(player.step((1, 0)));
+        assert!(player.step((1, 0)));
+        assert_eq!(player.position, door);
+        assert_eq!(player.map.tiles[door], Some(TileType::Door { open: true }));
+        assert_eq!(player.opened, vec![door]);
+    }
+
+    #[test]
+    fn fog_is_revealed_as_the_player_walks() {
+        let mut player = player_at(
+            &[
+                "######################",
+                "#....................#",
+                "######################",
+            ],
+            1,
+            1,
+        );
+        let idx = |x| player.map.xy_idx(x, 1);
+        let (first, far) = (idx(1), idx(15));
+        assert!(player.visible.contains(&idx(9)));
+        assert!(!player.explored[far]);
+        let ratio = player.explored_ratio();
+
+        for _ in 0..10 {
+            assert!(player.step((1, 0)));
+        }
+        assert!(player.visible.contains(&far) && player.explored[far]);
+        // Tiles out of sight stay explored
+        assert!(!player.visible.contains(&first) && player.explored[first]);
+        assert!(player.explored_ratio() > ratio);
+        assert!(player.visible.iter().all(|idx| player.explored[*idx]));
+    }
+}
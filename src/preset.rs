@@ -0,0 +1,238 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    algorithms::{Algorithm, Option},
+    map_builders::RegionRepair,
+};
+
+/// Folder the presets are read from and saved to.
+pub const PRESETS_DIR: &str = "presets";
+
+/// Named generation settings stored as JSON.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Preset {
+    pub name: String,
+    pub algorithm: Algorithm,
+    pub width: i32,
+    pub height: i32,
+    /// Seed of the map, a random one is used when missing.
+    #[serde(default)]
+    pub seed: std::option::Option<u64>,
+    /// Option values by key, missing ones keep their default.
+    #[serde(default)]
+    pub options: BTreeMap<String, i32>,
+    /// How disconnected regions are repaired, they change the map built from the seed.
+    #[serde(default)]
+    pub repair: RegionRepair,
+}
+
+impl Preset {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+        serde_json::from_str(&content)
+            .map_err(|err| format!("Invalid preset {}: {}", path.display(), err))
+    }
+
+    /// Reads every preset of a folder, sorted by name.
+    pub fn load_dir(dir: &Path) -> Vec<Result<Self, String>> {
+        let mut paths: Vec<PathBuf> = match fs::read_dir(dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+                .collect(),
+            Err(_) => return vec![],
+        };
+        paths.sort();
+        paths.iter().map(|path| Preset::load(path)).collect()
+    }
+
+    /// Writes the preset to `<dir>/<name>.json` and returns the path.
+    pub fn save(&self, dir: &Path) -> Result<PathBuf, String> {
+        if self.name.is_empty() || self.name.contains(['/', '\\']) {
+            return Err(format!("Invalid preset name '{}'", self.name));
+        }
+        let path = dir.join(format!("{}.json", self.name));
+        let content = serde_json::to_string_pretty(self).expect("Presets serialize to JSON");
+        fs::create_dir_all(dir)
+            .and_then(|_| fs::write(&path, content))
+            .map_err(|err| format!("Could not write {}: {}", path.display(), err))?;
+        Ok(path)
+    }
+
+    /// Encodes everything but the name as a URL query string, without the leading `?`.
+    pub fn to_query(&self) -> String {
+        let mut query = format!(
+            "algorithm={}&width={}&height={}",
+            self.algorithm.key(),
+            self.width,
+            self.height
+        );
+        if let Some(seed) = self.seed {
+            query += &format!("&seed={}", seed);
+        }
+        query += &format!("&repair={}", self.repair.key());
+        for (key, value) in self.options.iter() {
+            query += &format!("&{}={}", key, value);
+        }
+        query
+    }
+
+    /// Decodes a query string written by `to_query`, keys that are neither a parameter nor an
+    /// option of the algorithm are ignored.
+    pub fn from_query(query: &str) -> Result<Self, String> {
+        let mut preset = Preset {
+            name: "link".to_string(),
+            algorithm: Algorithm::None,
+            width: 60,
+            height: 40,
+            seed: None,
+            options: BTreeMap::new(),
+            repair: RegionRepair::default(),
+        };
+
+        let number = |key: &str, value: &str| {
+            value
+                .parse()
+                .map_err(|_| format!("Invalid {} '{}'", key, value))
+        };
+        let pairs: Vec<(&str, &str)> = query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .collect();
+        for (key, value) in pairs.iter().copied() {
+            match key {
+                "algorithm" => preset.algorithm = value.parse()?,
+                "width" => preset.width = number(key, value)?,
+                "height" => preset.height = number(key, value)?,
+                "repair" => preset.repair = value.parse()?,
+                "seed" => {
+                    preset.seed = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("Invalid seed '{}'", value))?,
+                    )
+                }
+                _ => {}
+            }
+        }
+
+        if preset.algorithm == Algorithm::None {
+            return Err("No algorithm in the query".to_string());
+        }
+        // Options are only known once the algorithm is
+        for option in preset.algorithm.options() {
+            if let Some((key, value)) = pairs.iter().find(|(key, _)| *key == option.key) {
+                preset.options.insert(key.to_string(), number(key, value)?);
+            }
+        }
+        Ok(preset)
+    }
+
+    /// Options of the algorithm with the values of the preset, missing values keep their default.
+    pub fn algorithm_options(&self) -> Vec<Option> {
+        let mut options = self.algorithm.options();
+        for option in options.iter_mut() {
+            if let Some(value) = self.options.get(option.key) {
+                option.value = *value;
+            }
+        }
+        options
+    }
+
+    /// Values of options by key.
+    pub fn option_values(options: &[Option]) -> BTreeMap<String, i32> {
+        options
+            .iter()
+            .map(|option| (option.key.to_string(), option.value))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Preset of the algorithm with every option one above its minimum.
+    fn preset(algorithm: Algorithm) -> Preset {
+        Preset {
+            name: "link".to_string(),
+            algorithm,
+            width: 45,
+            height: 30,
+            seed: Some(u64::MAX),
+            options: algorithm
+                .options()
+                .iter()
+                .map(|option| (option.key.to_string(), option.min + 1))
+                .collect(),
+            repair: RegionRepair::Drop(7),
+        }
+    }
+
+    #[test]
+    fn queries_keep_every_setting_of_every_algorithm() {
+        for algorithm in Algorithm::all() {
+            let preset = preset(algorithm);
+            let decoded = Preset::from_query(&preset.to_query()).unwrap();
+            assert!(decoded.algorithm == algorithm);
+            assert_eq!(decoded.name, preset.name);
+            assert_eq!((decoded.width, decoded.height), (45, 30));
+            assert_eq!(decoded.seed, preset.seed);
+            assert_eq!(decoded.options, preset.options);
+            assert!(!decoded.options.is_empty());
+            assert!(decoded.repair == RegionRepair::Drop(7));
+        }
+    }
+
+    #[test]
+    fn queries_without_seed_or_options_keep_the_defaults() {
+        let decoded = Preset::from_query("algorithm=drunkard").unwrap();
+        assert!(decoded.algorithm == Algorithm::Drunkard);
+        assert_eq!((decoded.width, decoded.height), (60, 40));
+        assert_eq!(decoded.seed, None);
+        assert!(decoded.options.is_empty());
+        assert!(decoded.repair == RegionRepair::Join);
+        let defaults = Preset::option_values(&Algorithm::Drunkard.options());
+        assert_eq!(
+            Preset::option_values(&decoded.algorithm_options()),
+            defaults
+        );
+    }
+
+    #[test]
+    fn unknown_keys_and_options_of_other_algorithms_are_ignored() {
+        let decoded =
+            Preset::from_query("algorithm=bsp&max_rooms=12&iterations=3&color=red&oops").unwrap();
+        assert_eq!(
+            decoded.options,
+            BTreeMap::from([("max_rooms".to_string(), 12)])
+        );
+    }
+
+    #[test]
+    fn invalid_queries_are_refused() {
+        let error = |query| Preset::from_query(query).err().unwrap();
+        assert_eq!(error(""), "No algorithm in the query");
+        assert_eq!(error("width=10"), "No algorithm in the query");
+        assert_eq!(error("algorithm=none"), "Unknown algorithm 'none'");
+        assert_eq!(error("algorithm=maze"), "Unknown algorithm 'maze'");
+        assert_eq!(error("algorithm=rooms&width=wide"), "Invalid width 'wide'");
+        assert_eq!(error("algorithm=rooms&height=-"), "Invalid height '-'");
+        assert_eq!(error("algorithm=rooms&seed=-1"), "Invalid seed '-1'");
+        assert_eq!(
+            error("algorithm=rooms&repair=fill"),
+            "Unknown region repair 'fill'"
+        );
+        assert_eq!(
+            error("algorithm=rooms&max_rooms=many"),
+            "Invalid max_rooms 'many'"
+        );
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::VecDeque;
+
+use bracket_pathfinding::prelude::BaseMap;
+use rand::{rngs::StdRng, seq::SliceRandom};
+
+use crate::{
+    analysis::find_path,
+    map::{Map, TileType},
+    room_graph::RoomGraph,
+};
+
+/// ASCII character keys are drawn with.
+pub const KEY_GLYPH: char = 'k';
+
+/// A locked door and the tile of the key opening it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lock {
+    pub door: usize,
+    pub key: usize,
+}
+
+/// Locks up to `max_locks` doors on the corridors of the way from the start to the exit, spread
+/// along it, and returns how many were placed.
+///
+/// A door is only locked where it cuts the way to the exit, and its key lies on a floor tile
+/// reachable before it, in a dead end or another room off the way when there's one. Locks making
+/// the level unsolvable are dropped.
+pub fn place_locks(map: &mut Map, graph: &RoomGraph, max_locks: usize, rng: &mut StdRng) -> usize {
+    let (start, exit) = match (map.start, map.exit) {
+        (Some(start), Some(exit)) => (start, exit),
+        _ => return 0,
+    };
+
+    // Stretches of corridor the way goes through, between two rooms
+    let mut crossings: Vec<Vec<usize>> = vec![];
+    let mut corridor = vec![];
+    for idx in find_path(map, start, exit) {
+        if graph.room_of[idx].is_none() {
+            corridor.push(idx);
+        } else if !corridor.is_empty() {
+            crossings.push(std::mem::take(&mut corridor));
+        }
+    }
+    if !corridor.is_empty() {
+        crossings.push(corridor);
+    }
+
+    let count = max_locks.min(crossings.len());
+    let mut picked: Vec<usize> = (1..=count)
+        .map(|i| i * crossings.len() / (count + 1))
+        .collect();
+    picked.dedup();
+
+    let critical_path = graph.critical_path(map);
+    let leaves = graph.leaves();
+    let mut placed = 0;
+    for crossing in picked.into_iter().map(|i| &crossings[i]) {
+        // Doors closer to the next room are tried first
+        for door in crossing.iter().rev().copied() {
+            if door == exit || map.spawns.iter().any(|spawn| spawn.idx == door) {
+                continue;
+            }
+            let before = reachable(map, start, &[door]);
+            if before[exit] {
+                continue;
+            }
+            let key = match pick_key_tile(map, graph, &critical_path, &leaves, &before, rng) {
+                Some(key) => key,
+                None => continue,
+            };
+
+            let tile = map.tiles[door].replace(TileType::Door { open: false });
+            map.locks.push(Lock { door, key });
+            if solve(map).is_some() {
+                placed += 1;
+                break;
+            }
+            map.locks.pop();
+            map.tiles[door] = tile;
+        }
+    }
+    placed
+}
+
+/// Locks in the order they are opened on the way from the start to the exit, `None` when the
+/// exit can't be reached with the keys found on the way.
+pub fn solve(map: &Map) -> Option<Vec<usize>> {
+    let (start, exit) = (map.start?, map.exit?);
+    let mut opened = vec![false; map.locks.len()];
+    let mut order = vec![];
+    loop {
+        let closed: Vec<usize> = map
+            .locks
+            .iter()
+            .zip(opened.iter())
+            .filter(|(_, opened)| !**opened)
+            .map(|(lock, _)| lock.door)
+            .collect();
+        let reached = reachable(map, start, &closed);
+        if reached[exit] {
+            return Some(order);
+        }
+
+        let found: Vec<usize> = (0..map.locks.len())
+            .filter(|lock| !opened[*lock] && reached[map.locks[*lock].key])
+            .collect();
+        if found.is_empty() {
+            return None;
+        }
+        for lock in found {
+            opened[lock] = true;
+            order.push(lock);
+        }
+    }
+}
+
+/// Tiles reachable from `start` with the same moves as the path finding, without stepping on
+/// the `closed` tiles.
+fn reachable(map: &Map, start: usize, closed: &[usize]) -> Vec<bool> {
+    let mut reached = vec![false; map.tiles.len()];
+    let mut queue = VecDeque::from([start]);
+    reached[start] = true;
+    while let Some(idx) = queue.pop_front() {
+        for (next, _) in map.get_available_exits(idx) {
+            if !reached[next] && !closed.contains(&next) {
+                reached[next] = true;
+                queue.push_back(next);
+            }
+        }
+    }
+    reached
+}
+
+/// Random free floor tile among the reachable ones, in a dead end off the critical path if any,
+/// otherwise in another room off it.
+fn pick_key_tile(
+    map: &Map,
+    graph: &RoomGraph,
+    critical_path: &[usize],
+    leaves: &[usize],
+    reached: &[bool],
+    rng: &mut StdRng,
+) -> Option<usize> {
+    let free: Vec<usize> = (0..map.tiles.len())
+        .filter(|idx| {
+            reached[*idx]
+                && map.tiles[*idx] == Some(TileType::Floor)
+                && Some(*idx) != map.start
+                && Some(*idx) != map.exit
+                && !map.spawns.iter().any(|spawn| spawn.idx == *idx)
+                && !map.locks.iter().any(|lock| lock.key == *idx)
+        })
+        .collect();
+    let side_rooms: Vec<usize> = free
+        .iter()
+        .copied()
+        .filter(|idx| graph.room_of[*idx].is_some_and(|room| !critical_path.contains(&room)))
+        .collect();
+    let dead_ends: Vec<usize> = side_rooms
+        .iter()
+        .copied()
+        .filter(|idx| graph.room_of[*idx].is_some_and(|room| leaves.contains(&room)))
+        .collect();
+
+    [dead_ends, side_rooms, free]
+        .iter()
+        .find(|tiles| !tiles.is_empty())
+        .and_then(|tiles| tiles.choose(rng).copied())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    /// Two rooms joined by a corridor of 3 tiles, from `(6, 3)` to `(8, 3)`.
+    const TWO_ROOMS: [&str; 7] = [
+        "###############",
+        "#.....###.....#",
+        "#.....###.....#",
+        "#..<.......>..#",
+        "#.....###.....#",
+        "#.....###.....#",
+        "###############",
+    ];
+
+    fn lock(map: &mut Map, door: (i32, i32), key: (i32, i32)) {
+        let door = map.xy_idx(door.0, door.1);
+        map.tiles[door] = Some(TileType::Door { open: false });
+        let key = map.xy_idx(key.0, key.1);
+        map.locks.push(Lock { door, key });
+    }
+
+    #[test]
+    fn levels_without_locks_are_solved_right_away() {
+        let map = Map::from_rows(&TWO_ROOMS);
+        assert_eq!(solve(&map), Some(vec![]));
+
+        let mut map = Map::from_rows(&TWO_ROOMS);
+        map.exit = None;
+        assert_eq!(solve(&map), None);
+    }
+
+    #[test]
+    fn keys_behind_their_own_door_are_unsolvable() {
+        let mut map = Map::from_rows(&TWO_ROOMS);
+        lock(&mut map, (7, 3), (12, 1));
+        assert_eq!(solve(&map), None);
+
+        // Two doors whose keys are behind each other
+        let mut map = Map::from_rows(&TWO_ROOMS);
+        lock(&mut map, (6, 3), (8, 3));
+        lock(&mut map, (8, 3), (7, 3));
+        assert_eq!(solve(&map), None);
+    }
+
+    #[test]
+    fn locks_are_opened_in_the_order_their_keys_are_found() {
+        let mut map = Map::from_rows(&TWO_ROOMS);
+        lock(&mut map, (8, 3), (7, 3));
+        lock(&mut map, (6, 3), (1, 1));
+        assert_eq!(solve(&map), Some(vec![1, 0]));
+    }
+
+    #[test]
+    fn locks_are_placed_on_the_corridor_with_the_key_before_them() {
+        let mut map = Map::from_rows(&TWO_ROOMS);
+        let graph = RoomGraph::detect(&map);
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(place_locks(&mut map, &graph, 3, &mut rng), 1);
+
+        let lock = map.locks[0].clone();
+        let (x, y) = map.idx_xy(lock.door);
+        assert!((6..=8).contains(&x) && y == 3);
+        assert_eq!(map.tiles[lock.door], Some(TileType::Door { open: false }));
+        assert_eq!(map.tiles[lock.key], Some(TileType::Floor));
+        assert!(map.idx_xy(lock.key).0 < x);
+        assert_eq!(solve(&map), Some(vec![0]));
+    }
+
+    #[test]
+    fn nothing_is_locked_without_a_corridor_on_the_way() {
+        let mut map = Map::from_rows(&TWO_ROOMS);
+        let graph = RoomGraph::detect(&map);
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(place_locks(&mut map, &graph, 0, &mut rng), 0);
+
+        // Start and exit in the same room
+        map.exit = Some(map.xy_idx(2, 4));
+        assert_eq!(place_locks(&mut map, &graph, 3, &mut rng), 0);
+        // The exit is never locked, even on a corridor tile such as the corner of a room
+        map.exit = Some(map.xy_idx(1, 5));
+        assert_eq!(place_locks(&mut map, &graph, 3, &mut rng), 0);
+        map.exit = None;
+        assert_eq!(place_locks(&mut map, &graph, 3, &mut rng), 0);
+        assert!(map.locks.is_empty());
+        assert!(!map.tiles.contains(&Some(TileType::Door { open: false })));
+    }
+}
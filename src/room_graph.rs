@@ -0,0 +1,383 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::{
+    analysis::{find_path, DIAGONAL, ORTHOGONAL},
+    map::Map,
+    map_builders::{centers, Rect},
+};
+
+/// Tiles between the walls and the middle of a detected room, narrower places are corridors.
+const ROOM_DEPTH: usize = 2;
+/// Fewest tiles of a detected room, smaller ones are part of the corridors around them.
+const MIN_ROOM_SIZE: usize = 9;
+
+/// A room of the graph and its walkable tiles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoomNode {
+    pub tiles: Vec<usize>,
+    /// Tile of the room closest to its middle.
+    pub center: usize,
+}
+
+/// Two rooms joined by a corridor, or touching each other when it's empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoomEdge {
+    pub from: usize,
+    pub to: usize,
+    pub corridor: Vec<usize>,
+}
+
+/// Rooms of a map and the corridors between them.
+#[derive(Debug, Clone)]
+pub struct RoomGraph {
+    pub nodes: Vec<RoomNode>,
+    /// Room of every tile, `None` for corridors and walls.
+    pub room_of: Vec<Option<usize>>,
+    pub edges: Vec<RoomEdge>,
+}
+
+impl RoomGraph {
+    /// Graph of the rooms placed by the builder, or of the rooms found in the tiles when it
+    /// placed none.
+    pub fn new(map: &Map, rooms: Option<&[Rect]>) -> Self {
+        match rooms.filter(|rooms| !rooms.is_empty()) {
+            Some(rooms) => Self::from_rooms(map, rooms),
+            None => Self::detect(map),
+        }
+    }
+
+    /// Graph of the rooms placed by a builder, a walkable tile belongs to the first room
+    /// containing it and every other one is part of a corridor.
+    pub fn from_rooms(map: &Map, rooms: &[Rect]) -> Self {
+        let mut room_of = vec![None; map.tiles.len()];
+        for (id, room) in rooms.iter().enumerate() {
+            for (idx, room_of) in room_of.iter_mut().enumerate() {
+                let (x, y) = map.idx_xy(idx);
+                if room_of.is_none() && room.contains(x, y) && map.is_walkable(idx) {
+                    *room_of = Some(id);
+                }
+            }
+        }
+        Self::from_room_of(map, room_of, rooms.len())
+    }
+
+    /// Graph of the rooms found in the tiles of any map.
+    ///
+    /// Walkable tiles at least `ROOM_DEPTH` tiles away from every wall are the middle of a room,
+    /// and a room spreads one tile around it. Passages too narrow to hold a middle are the
+    /// chokepoints between rooms and become corridors, as do rooms smaller than `MIN_ROOM_SIZE`.
+    pub fn detect(map: &Map) -> Self {
+        let distances = wall_distances(map);
+        let mut room_of: Vec<Option<usize>> = vec![None; map.tiles.len()];
+        let mut rooms: Vec<Vec<usize>> = vec![];
+        for start in 0..map.tiles.len() {
+            if distances[start] < ROOM_DEPTH || room_of[start].is_some() {
+                continue;
+            }
+
+            let id = rooms.len();
+            let mut middle = vec![start];
+            let mut queue = VecDeque::from([start]);
+            room_of[start] = Some(id);
+            while let Some(idx) = queue.pop_front() {
+                for next in neighbors(map, idx) {
+                    if distances[next] >= ROOM_DEPTH && room_of[next].is_none() {
+                        room_of[next] = Some(id);
+                        middle.push(next);
+                        queue.push_back(next);
+                    }
+                }
+            }
+            rooms.push(middle);
+        }
+
+        // Rooms grow over the tiles around their middle, the first one reaching a tile keeps it
+        for (id, middle) in rooms.iter_mut().enumerate() {
+            let mut border = vec![];
+            for idx in middle.iter() {
+                for next in neighbors(map, *idx) {
+                    if room_of[next].is_none() {
+                        room_of[next] = Some(id);
+                        border.push(next);
+                    }
+                }
+            }
+            middle.extend(border);
+        }
+
+        // Small rooms are dropped and the others numbered again
+        let mut ids = vec![None; rooms.len()];
+        let mut count = 0;
+        for (id, tiles) in rooms.iter().enumerate() {
+            if tiles.len() >= MIN_ROOM_SIZE {
+                ids[id] = Some(count);
+                count += 1;
+            }
+        }
+        for room in room_of.iter_mut() {
+            *room = room.and_then(|id| ids[id]);
+        }
+        Self::from_room_of(map, room_of, count)
+    }
+
+    /// Nodes of `count` rooms from the room of every tile, joined by the corridors between them.
+    fn from_room_of(map: &Map, room_of: Vec<Option<usize>>, count: usize) -> Self {
+        let mut tiles = vec![vec![]; count];
+        for (idx, room) in room_of.iter().enumerate() {
+            if let Some(room) = room {
+                tiles[*room].push(idx);
+            }
+        }
+        let mut centers = centers(map, &room_of);
+        centers.resize(count, 0);
+        let nodes = tiles
+            .into_iter()
+            .zip(centers)
+            .map(|(tiles, center)| RoomNode { tiles, center })
+            .collect();
+
+        // Sorted by rooms so the same map always gives the same edges
+        let mut edges: BTreeMap<(usize, usize), Vec<usize>> = BTreeMap::new();
+        let mut visited = vec![false; map.tiles.len()];
+        for start in 0..map.tiles.len() {
+            if !map.is_walkable(start) || visited[start] {
+                continue;
+            }
+            if let Some(room) = room_of[start] {
+                // Rooms touching each other are joined without a corridor
+                for next in neighbors(map, start) {
+                    if let Some(other) = room_of[next].filter(|other| *other != room) {
+                        edges.entry((room.min(other), room.max(other))).or_default();
+                    }
+                }
+                continue;
+            }
+
+            let (corridor, touched) = flood_corridor(map, &room_of, &mut visited, start);
+            for (i, a) in touched.iter().enumerate() {
+                for b in touched.iter().skip(i + 1) {
+                    let tiles = edges.entry((*a.min(b), *a.max(b))).or_default();
+                    if tiles.is_empty() || corridor.len() < tiles.len() {
+                        *tiles = corridor.clone();
+                    }
+                }
+            }
+        }
+
+        Self {
+            nodes,
+            room_of,
+            edges: edges
+                .into_iter()
+                .map(|((from, to), corridor)| RoomEdge { from, to, corridor })
+                .collect(),
+        }
+    }
+
+    /// Rooms joined to a room by an edge.
+    pub fn neighbors(&self, room: usize) -> Vec<usize> {
+        self.edges
+            .iter()
+            .filter_map(|edge| match (edge.from == room, edge.to == room) {
+                (true, _) => Some(edge.to),
+                (_, true) => Some(edge.from),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Dead end rooms, joined to a single other room.
+    pub fn leaves(&self) -> Vec<usize> {
+        (0..self.nodes.len())
+            .filter(|room| self.neighbors(*room).len() == 1)
+            .collect()
+    }
+
+    /// Rooms walked through on the shortest path from the start to the exit, in order.
+    pub fn critical_path(&self, map: &Map) -> Vec<usize> {
+        let path = match (map.start, map.exit) {
+            (Some(start), Some(exit)) => find_path(map, start, exit),
+            _ => vec![],
+        };
+        let mut rooms: Vec<usize> = vec![];
+        for room in path.iter().filter_map(|idx| self.room_of[*idx]) {
+            if rooms.last() != Some(&room) {
+                rooms.push(room);
+            }
+        }
+        rooms
+    }
+}
+
+/// Walkable tiles orthogonally next to a tile.
+fn neighbors(map: &Map, idx: usize) -> impl Iterator<Item = usize> + '_ {
+    let (x, y) = map.idx_xy(idx);
+    ORTHOGONAL
+        .iter()
+        .map(move |(dx, dy)| (x + dx, y + dy))
+        .filter(|(x, y)| *x >= 0 && *x < map.width && *y >= 0 && *y < map.height)
+        .map(|(x, y)| map.xy_idx(x, y))
+        .filter(|idx| map.is_walkable(*idx))
+}
+
+/// Tiles between every tile and the closest wall or edge of the map, 0 for walls.
+fn wall_distances(map: &Map) -> Vec<usize> {
+    let mut distances = vec![usize::MAX; map.tiles.len()];
+    let mut queue = VecDeque::new();
+    for (idx, distance) in distances.iter_mut().enumerate() {
+        let (x, y) = map.idx_xy(idx);
+        if !map.is_walkable(idx) {
+            *distance = 0;
+            queue.push_back(idx);
+        } else if x == 0 || y == 0 || x == map.width - 1 || y == map.height - 1 {
+            *distance = 1;
+            queue.push_back(idx);
+        }
+    }
+
+    while let Some(idx) = queue.pop_front() {
+        let (x, y) = map.idx_xy(idx);
+        for (dx, dy) in ORTHOGONAL.iter().chain(DIAGONAL.iter()) {
+            let (nx, ny) = (x + dx, y + dy);
+            if nx < 0 || nx >= map.width || ny < 0 || ny >= map.height {
+                continue;
+            }
+            let next = map.xy_idx(nx, ny);
+            if distances[next] == usize::MAX {
+                distances[next] = distances[idx] + 1;
+                queue.push_back(next);
+            }
+        }
+    }
+    distances
+}
+
+/// Tiles of the corridor holding `start` and the rooms it opens on, sorted.
+fn flood_corridor(
+    map: &Map,
+    room_of: &[Option<usize>],
+    visited: &mut [bool],
+    start: usize,
+) -> (Vec<usize>, Vec<usize>) {
+    let mut corridor = vec![];
+    let mut touched = vec![];
+    let mut queue = VecDeque::from([start]);
+    visited[start] = true;
+    while let Some(idx) = queue.pop_front() {
+        corridor.push(idx);
+        for next in neighbors(map, idx) {
+            match room_of[next] {
+                Some(room) if !touched.contains(&room) => touched.push(room),
+                Some(_) => {}
+                None if !visited[next] => {
+                    visited[next] = true;
+                    queue.push_back(next);
+                }
+                None => {}
+            }
+        }
+    }
+    touched.sort_unstable();
+    (corridor, touched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two rooms of 5x5 tiles joined by a corridor of 3 tiles.
+    const TWO_ROOMS: [&str; 7] = [
+        "###############",
+        "#.....###.....#",
+        "#.....###.....#",
+        "#..<.......>..#",
+        "#.....###.....#",
+        "#.....###.....#",
+        "###############",
+    ];
+
+    #[test]
+    fn narrow_passages_hold_no_rooms() {
+        let map = Map::from_rows(&[
+            "#########",
+            "#<......#",
+            "#######.#",
+            "#>......#",
+            "#########",
+        ]);
+        let graph = RoomGraph::detect(&map);
+        assert!(graph.nodes.is_empty() && graph.edges.is_empty());
+        assert!(graph.room_of.iter().all(Option::is_none));
+        assert!(graph.leaves().is_empty());
+        assert!(graph.critical_path(&map).is_empty());
+    }
+
+    #[test]
+    fn an_open_space_is_a_single_room() {
+        let map = Map::from_rows(&[
+            "#######", "#.....#", "#.....#", "#..<..#", "#.....#", "#...>.#", "#######",
+        ]);
+        let graph = RoomGraph::detect(&map);
+        assert_eq!(graph.nodes.len(), 1);
+        // The middle grows orthogonally, the corners are left out
+        assert_eq!(graph.nodes[0].tiles.len(), 21);
+        assert!(graph.room_of[map.xy_idx(1, 1)].is_none());
+        assert_eq!(graph.nodes[0].center, map.xy_idx(3, 3));
+        assert!(graph.edges.is_empty() && graph.leaves().is_empty());
+        assert_eq!(graph.critical_path(&map), vec![0]);
+    }
+
+    #[test]
+    fn rooms_are_joined_by_the_corridor_between_them() {
+        let map = Map::from_rows(&TWO_ROOMS);
+        let graph = RoomGraph::detect(&map);
+        assert_eq!(graph.nodes.len(), 2);
+        assert!(graph.nodes.iter().all(|node| node.tiles.len() == 21));
+        let corridor: Vec<usize> = (6..9).map(|x| map.xy_idx(x, 3)).collect();
+        assert_eq!(
+            graph.edges,
+            vec![RoomEdge {
+                from: 0,
+                to: 1,
+                corridor
+            }]
+        );
+        assert_eq!(graph.neighbors(0), vec![1]);
+        assert_eq!(graph.leaves(), vec![0, 1]);
+        assert_eq!(graph.critical_path(&map), vec![0, 1]);
+    }
+
+    #[test]
+    fn small_rooms_become_corridors() {
+        // The middle of the right room is too small to be a room of its own
+        let map = Map::from_rows(&[
+            "##############",
+            "#.....########",
+            "#.....###...##",
+            "#..<.......>.#",
+            "#.....###...##",
+            "#.....########",
+            "##############",
+        ]);
+        let graph = RoomGraph::detect(&map);
+        assert_eq!(graph.nodes.len(), 1);
+        assert!(graph.room_of[map.exit.unwrap()].is_none());
+        assert!(graph.edges.is_empty());
+    }
+
+    #[test]
+    fn builder_rooms_are_used_when_there_are_some() {
+        let map = Map::from_rows(&TWO_ROOMS);
+        // Overlapping rooms, the shared tiles belong to the first one
+        let rooms = [Rect::new(1, 1, 4, 4), Rect::new(4, 1, 4, 4)];
+        let graph = RoomGraph::new(&map, Some(&rooms));
+        assert_eq!(graph.nodes.len(), 2);
+        assert_eq!(graph.room_of[map.xy_idx(5, 3)], Some(0));
+        assert_eq!(graph.room_of[map.xy_idx(6, 3)], Some(1));
+        assert_eq!(graph.edges.len(), 1);
+        assert!(graph.edges[0].corridor.is_empty());
+
+        let detected = RoomGraph::new(&map, Some(&[]));
+        assert_eq!(detected.room_of, RoomGraph::detect(&map).room_of);
+    }
+}
@@ -0,0 +1,42 @@
+use bevy::prelude::*;
+use procedural_map::preset::Preset;
+use wasm_bindgen::JsValue;
+
+/// Settings encoded in the query string of the page, if any.
+pub fn read_link() -> Option<Preset> {
+    let search = web_sys::window()?.location().search().ok()?;
+    let query = search.strip_prefix('?')?;
+    match Preset::from_query(query) {
+        Ok(preset) => Some(preset),
+        Err(err) => {
+            warn!("Ignoring the page link: {}", err);
+            None
+        }
+    }
+}
+
+/// Replaces the query string of the page without reloading it.
+pub fn update_link(preset: &Preset) {
+    let url = format!("?{}", preset.to_query());
+    if let Some(history) = web_sys::window().and_then(|window| window.history().ok()) {
+        if history
+            .replace_state_with_url(&JsValue::NULL, "", Some(&url))
+            .is_err()
+        {
+            warn!("Could not update the page link");
+        }
+    }
+}
+
+/// Copies the address of the page to the clipboard.
+pub fn copy_link() {
+    let window = match web_sys::window() {
+        Some(window) => window,
+        None => return,
+    };
+    match (window.location().href(), window.navigator().clipboard()) {
+        // The promise is dropped, the browser copies the text anyway
+        (Ok(href), Some(clipboard)) => drop(clipboard.write_text(&href)),
+        _ => warn!("The clipboard isn't available"),
+    }
+}
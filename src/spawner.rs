@@ -0,0 +1,291 @@
+use rand::{rngs::StdRng, Rng};
+use serde::Serialize;
+
+use crate::{
+    analysis::regions,
+    map::{Map, TileType},
+    map_builders::Rect,
+};
+
+/// What a spawn is, the game decides how to turn it into an entity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpawnKind {
+    Monster,
+    Item,
+    Trap,
+}
+
+/// Something placed on a tile of a finished map.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Spawn {
+    pub idx: usize,
+    pub name: String,
+    pub kind: SpawnKind,
+    /// ASCII character it's drawn with.
+    pub glyph: char,
+}
+
+/// An entry of a spawn table, its weight grows by `per_depth` on every level below the first.
+#[derive(Debug, Clone)]
+pub struct SpawnEntry {
+    pub name: String,
+    pub kind: SpawnKind,
+    pub glyph: char,
+    pub weight: i32,
+    pub per_depth: i32,
+    /// First level the entry can be rolled on, the top of the dungeon being 1.
+    pub min_depth: i32,
+}
+
+impl SpawnEntry {
+    /// Weight of the entry on a level, 0 above its first level.
+    pub fn weight_at(&self, depth: i32) -> i32 {
+        if depth < self.min_depth {
+            return 0;
+        }
+        (self.weight + self.per_depth * (depth - 1)).max(0)
+    }
+}
+
+/// Entries rolled with a chance proportional to their weight at the depth of the level.
+#[derive(Debug, Clone)]
+pub struct SpawnTable {
+    pub entries: Vec<SpawnEntry>,
+}
+
+impl Default for SpawnTable {
+    /// Goblins and potions near the top, orcs, ogres and traps taking over further down.
+    fn default() -> Self {
+        let entry = |name: &str, kind, glyph, weight, per_depth, min_depth| SpawnEntry {
+            name: name.to_string(),
+            kind,
+            glyph,
+            weight,
+            per_depth,
+            min_depth,
+        };
+        Self {
+            entries: vec![
+                entry("Goblin", SpawnKind::Monster, 'g', 10, 0, 1),
+                entry("Orc", SpawnKind::Monster, 'o', 1, 2, 1),
+                entry("Ogre", SpawnKind::Monster, 'O', 0, 1, 3),
+                entry("Health potion", SpawnKind::Item, '!', 7, 0, 1),
+                entry("Scroll", SpawnKind::Item, '?', 2, 1, 1),
+                entry("Sword", SpawnKind::Item, ')', 1, 1, 2),
+                entry("Bear trap", SpawnKind::Trap, '^', 2, 1, 2),
+            ],
+        }
+    }
+}
+
+impl SpawnTable {
+    /// Picks an entry for a level, `None` when every weight is 0 at that depth.
+    pub fn roll(&self, depth: i32, rng: &mut StdRng) -> Option<&SpawnEntry> {
+        let total: i32 = self.entries.iter().map(|e| e.weight_at(depth)).sum();
+        if total <= 0 {
+            return None;
+        }
+
+        let mut roll = rng.gen_range(0..total);
+        for entry in self.entries.iter() {
+            let weight = entry.weight_at(depth);
+            if roll < weight {
+                return Some(entry);
+            }
+            roll -= weight;
+        }
+        None
+    }
+}
+
+/// Walkable tiles of the areas spawns are spread over: the rooms of the map when it has some,
+/// otherwise the areas it was split in, or its connected regions if it wasn't. A tile belongs to
+/// a single area, the first room containing it.
+pub fn spawn_areas(map: &Map, rooms: Option<&[Rect]>) -> Vec<Vec<usize>> {
+    if let Some(rooms) = rooms.filter(|rooms| !rooms.is_empty()) {
+        let mut assigned = vec![false; map.tiles.len()];
+        return rooms
+            .iter()
+            .map(|room| {
+                let mut area = vec![];
+                for (idx, assigned) in assigned.iter_mut().enumerate() {
+                    let (x, y) = map.idx_xy(idx);
+                    if !*assigned && room.contains(x, y) && map.is_walkable(idx) {
+                        *assigned = true;
+                        area.push(idx);
+                    }
+                }
+                area
+            })
+            .collect();
+    }
+
+    if map.areas.is_empty() {
+        return regions(map, true);
+    }
+    let count = map.areas.iter().flatten().max().map_or(0, |id| id + 1);
+    let mut areas = vec![vec![]; count];
+    for (idx, id) in map.areas.iter().enumerate() {
+        if let Some(id) = id.filter(|_| map.is_walkable(idx)) {
+            areas[id].push(idx);
+        }
+    }
+    areas
+}
+
+/// Places up to `max_per_area` spawns rolled from the table in every area, on floor tiles that
+/// are neither the start nor the exit. The area holding the start is left empty.
+pub fn spawn(
+    map: &Map,
+    areas: &[Vec<usize>],
+    table: &SpawnTable,
+    depth: i32,
+    max_per_area: usize,
+    rng: &mut StdRng,
+) -> Vec<Spawn> {
+    let mut used = vec![false; map.tiles.len()];
+    let mut spawns = vec![];
+    for area in areas {
+        if map.start.is_some_and(|start| area.contains(&start)) {
+            continue;
+        }
+
+        let mut tiles: Vec<usize> = area
+            .iter()
+            .copied()
+            .filter(|idx| {
+                map.tiles[*idx] == Some(TileType::Floor)
+                    && Some(*idx) != map.start
+                    && Some(*idx) != map.exit
+                    && !used[*idx]
+            })
+            .collect();
+        let count = rng.gen_range(0..=max_per_area).min(tiles.len());
+        for _ in 0..count {
+            let entry = match table.roll(depth, rng) {
+                Some(entry) => entry,
+                None => break,
+            };
+            let idx = tiles.swap_remove(rng.gen_range(0..tiles.len()));
+            used[idx] = true;
+            spawns.push(Spawn {
+                idx,
+                name: entry.name.clone(),
+                kind: entry.kind,
+                glyph: entry.glyph,
+            });
+        }
+    }
+    spawns
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    /// Two rooms joined by a corridor, the start in the left one.
+    const TWO_ROOMS: [&str; 7] = [
+        "###############",
+        "#.....###.....#",
+        "#.....###.....#",
+        "#..<.......>..#",
+        "#.....###.....#",
+        "#.....###.....#",
+        "###############",
+    ];
+
+    fn entry(name: &str, weight: i32, per_depth: i32, min_depth: i32) -> SpawnEntry {
+        SpawnEntry {
+            name: name.to_string(),
+            kind: SpawnKind::Monster,
+            glyph: 'm',
+            weight,
+            per_depth,
+            min_depth,
+        }
+    }
+
+    #[test]
+    fn weights_grow_with_the_depth_from_the_first_level() {
+        let rat = entry("Rat", 5, -2, 1);
+        assert_eq!(
+            (1..=4).map(|d| rat.weight_at(d)).collect::<Vec<_>>(),
+            vec![5, 3, 1, 0]
+        );
+        let dragon = entry("Dragon", 0, 2, 3);
+        assert_eq!(
+            (1..=4).map(|d| dragon.weight_at(d)).collect::<Vec<_>>(),
+            vec![0, 0, 4, 6]
+        );
+    }
+
+    #[test]
+    fn rolls_only_pick_entries_with_a_weight() {
+        let mut rng = StdRng::seed_from_u64(1);
+        assert!(SpawnTable { entries: vec![] }.roll(1, &mut rng).is_none());
+        let table = SpawnTable {
+            entries: vec![entry("Rat", 0, 0, 1), entry("Dragon", 1, 0, 3)],
+        };
+        assert!(table.roll(2, &mut rng).is_none());
+        for _ in 0..20 {
+            assert_eq!(table.roll(3, &mut rng).unwrap().name, "Dragon");
+        }
+    }
+
+    #[test]
+    fn builder_rooms_are_the_areas_and_tiles_belong_to_the_first_one() {
+        let map = Map::from_rows(&TWO_ROOMS);
+        let rooms = [Rect::new(1, 1, 4, 4), Rect::new(4, 1, 4, 4)];
+        let areas = spawn_areas(&map, Some(&rooms));
+        assert_eq!(areas.len(), 2);
+        assert_eq!(areas[0].len(), 25);
+        // The second room overlaps the first one and is mostly walls
+        assert_eq!(
+            areas[1],
+            vec![map.xy_idx(6, 3), map.xy_idx(7, 3), map.xy_idx(8, 3)]
+        );
+    }
+
+    #[test]
+    fn maps_without_rooms_use_their_areas_or_regions() {
+        let mut map = Map::from_rows(&["#######", "#..#..#", "#######"]);
+        assert_eq!(spawn_areas(&map, Some(&[])), regions(&map, true));
+        assert_eq!(spawn_areas(&map, None).len(), 2);
+
+        map.areas = vec![None; map.tiles.len()];
+        for x in 1..6 {
+            let idx = map.xy_idx(x, 1);
+            map.areas[idx] = Some((x as usize - 1) / 2);
+        }
+        let wall = map.xy_idx(3, 1);
+        let areas = spawn_areas(&map, None);
+        assert_eq!(areas.len(), 3);
+        assert!(areas.iter().all(|area| !area.contains(&wall)));
+        assert_eq!(areas[1], vec![map.xy_idx(4, 1)]);
+    }
+
+    #[test]
+    fn spawns_stay_out_of_the_start_room_and_off_the_exit() {
+        let map = Map::from_rows(&TWO_ROOMS);
+        let areas = spawn_areas(&map, Some(&[Rect::new(1, 1, 4, 4), Rect::new(9, 1, 4, 4)]));
+        let table = SpawnTable::default();
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..20 {
+            let spawns = spawn(&map, &areas, &table, 1, 25, &mut rng);
+            for spawn in spawns.iter() {
+                assert!(areas[1].contains(&spawn.idx));
+                assert_ne!(Some(spawn.idx), map.exit);
+                assert_eq!(
+                    spawns.iter().filter(|other| other.idx == spawn.idx).count(),
+                    1
+                );
+                assert!(!["Ogre", "Sword", "Bear trap"].contains(&spawn.name.as_str()));
+            }
+        }
+        assert!(spawn(&map, &areas, &table, 1, 0, &mut rng).is_empty());
+        assert!(spawn(&map, &[], &table, 1, 5, &mut rng).is_empty());
+    }
+}
@@ -1,11 +1,58 @@
 use std::time::Duration;
 
-use crate::algorithms::{Algorithm, Option};
-use crate::States;
+use crate::overlay::{MapAnalysis, OverlaySettings};
+use crate::play::Player;
+use crate::{ComparisonView, CurrentComparison, CurrentDungeon, States, Timeline};
 use bevy::prelude::*;
 use bevy_egui::EguiContext;
 use bevy_egui::*;
 use iyes_loopless::prelude::*;
+use procedural_map::algorithms::{Algorithm, Option};
+use procedural_map::dungeon::Dungeon;
+#[cfg(not(target_arch = "wasm32"))]
+use procedural_map::export::export_animation;
+use procedural_map::export::Atlas;
+use procedural_map::generator::{generate, Constraint, Fitness, GenerationRequest};
+use procedural_map::map_builders::{phases, RegionRepair, SnapshotPolicy};
+use procedural_map::metrics::MapMetrics;
+use procedural_map::preset::Preset;
+use procedural_map::spawner::SpawnTable;
+
+/// Settings of a level below the first one.
+#[derive(Clone)]
+pub struct DepthConfig {
+    pub algorithm: Algorithm,
+    pub width: i32,
+    pub height: i32,
+    pub options: Vec<Option>,
+}
+
+/// Settings of the map generated next to the shown level to compare them.
+#[derive(Clone)]
+pub struct ComparisonConfig {
+    pub level: DepthConfig,
+    /// Seed used instead of the one of the first level.
+    pub seed: std::option::Option<u64>,
+}
+
+/// Size of a gallery thumbnail in pixels.
+const THUMBNAIL_SIZE: u32 = 96;
+const GALLERY_COLUMNS: usize = 8;
+/// Maps of the gallery generated every frame, so the app keeps responding while it fills up.
+const GALLERY_MAPS_PER_FRAME: usize = 2;
+
+/// Final maps of random seeds generated with the settings of the first level.
+pub struct Gallery {
+    /// Settings of the maps, every one gets a random seed.
+    request: GenerationRequest,
+    atlas: Atlas,
+    /// Maps still to generate.
+    remaining: usize,
+    thumbnails: Vec<(u64, egui::TextureHandle)>,
+    /// Maps that could not be generated, with the last reason.
+    failed: usize,
+    error: std::option::Option<String>,
+}
 
 pub struct Config {
     pub offset: f32,
@@ -13,6 +60,41 @@ pub struct Config {
     pub width: i32,
     pub height: i32,
     pub options: Vec<Option>,
+    /// Seed used instead of a random one.
+    pub seed: std::option::Option<u64>,
+    pub repair: RegionRepair,
+    pub candidates: usize,
+    pub fitness: Fitness,
+    /// Every constraint and whether it's enabled.
+    pub constraints: Vec<(Constraint, bool)>,
+    pub max_attempts: usize,
+    /// Levels generated below the first one, from top to bottom.
+    pub depths: Vec<DepthConfig>,
+    /// Most monsters, items and traps placed in a room, none when 0.
+    pub spawns_per_room: usize,
+    /// Most locked doors placed on the way to the exit of a level.
+    pub locks: usize,
+    pub comparison: std::option::Option<ComparisonConfig>,
+    /// Level of the dungeon being shown.
+    pub level: usize,
+    /// Why the last generation failed.
+    pub error: std::option::Option<String>,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub presets: Vec<Preset>,
+    /// Name the current settings are saved under.
+    pub preset_name: String,
+    /// Result of the last preset operation.
+    #[cfg(not(target_arch = "wasm32"))]
+    preset_status: String,
+    /// Result of the last dungeon export.
+    #[cfg(not(target_arch = "wasm32"))]
+    export_status: String,
+    /// Result of the last playback export.
+    #[cfg(not(target_arch = "wasm32"))]
+    playback_status: String,
+    pub snapshots: SnapshotPolicy,
+    /// Number of maps generated for the gallery.
+    pub gallery_size: usize,
     delay: u64,
     pub speed_timer: Timer,
 }
@@ -25,18 +107,210 @@ impl Default for Config {
             height: 40,
             offset: 0.,
             options: vec![],
+            seed: None,
+            repair: RegionRepair::default(),
+            candidates: 1,
+            fitness: Fitness::default(),
+            constraints: Constraint::all().into_iter().map(|c| (c, false)).collect(),
+            max_attempts: 10,
+            depths: vec![],
+            spawns_per_room: 0,
+            locks: 0,
+            comparison: None,
+            level: 0,
+            error: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            presets: vec![],
+            preset_name: String::new(),
+            #[cfg(not(target_arch = "wasm32"))]
+            preset_status: String::new(),
+            #[cfg(not(target_arch = "wasm32"))]
+            export_status: String::new(),
+            #[cfg(not(target_arch = "wasm32"))]
+            playback_status: String::new(),
+            snapshots: SnapshotPolicy::default(),
+            gallery_size: 16,
             delay: 100,
             speed_timer: Timer::new(Duration::from_millis(100), true),
         }
     }
 }
 
-pub fn draw_ui(mut cmds: Commands, mut egui_ctx: ResMut<EguiContext>, mut cfg: ResMut<Config>) {
+impl Config {
+    pub fn request(&self) -> GenerationRequest {
+        GenerationRequest {
+            algorithm: self.algorithm,
+            width: self.width,
+            height: self.height,
+            options: self.options.clone(),
+            repair: self.repair,
+            seed: self.seed.unwrap_or_else(rand::random),
+            candidates: self.candidates,
+            fitness: self.fitness,
+            constraints: self
+                .constraints
+                .iter()
+                .filter(|(_, enabled)| *enabled)
+                .map(|(constraint, _)| *constraint)
+                .collect(),
+            max_attempts: self.max_attempts,
+            snapshots: self.snapshots,
+        }
+    }
+
+    /// Current settings as a preset building the map of `seed`.
+    pub fn preset(&self, seed: std::option::Option<u64>) -> Preset {
+        Preset {
+            name: self.preset_name.trim().to_string(),
+            algorithm: self.algorithm,
+            width: self.width,
+            height: self.height,
+            seed,
+            options: Preset::option_values(&self.options),
+            repair: self.repair,
+        }
+    }
+
+    pub fn apply_preset(&mut self, preset: &Preset) {
+        self.algorithm = preset.algorithm;
+        self.width = preset.width;
+        self.height = preset.height;
+        self.seed = preset.seed;
+        self.options = preset.algorithm_options();
+        self.repair = preset.repair;
+        self.preset_name = preset.name.clone();
+    }
+
+    /// Whether every level and the compared map have an algorithm with valid options.
+    pub fn is_valid(&self) -> bool {
+        let valid = |algorithm: Algorithm, options: &[Option]| {
+            algorithm != Algorithm::None && algorithm.validate(options).is_empty()
+        };
+        valid(self.algorithm, &self.options)
+            && self
+                .depths
+                .iter()
+                .chain(self.comparison.iter().map(|comparison| &comparison.level))
+                .all(|depth| valid(depth.algorithm, &depth.options))
+    }
+
+    /// Request of the compared map, using the seed of the first level unless it has its own.
+    pub fn comparison_request(&self, seed: u64) -> std::option::Option<GenerationRequest> {
+        self.comparison
+            .as_ref()
+            .map(|comparison| GenerationRequest {
+                algorithm: comparison.level.algorithm,
+                width: comparison.level.width,
+                height: comparison.level.height,
+                options: comparison.level.options.clone(),
+                seed: comparison.seed.unwrap_or(seed),
+                ..self.request()
+            })
+    }
+
+    /// Settings of the first level, new levels and compared maps start with them.
+    pub fn first_level(&self) -> DepthConfig {
+        DepthConfig {
+            algorithm: self.algorithm,
+            width: self.width,
+            height: self.height,
+            options: self.options.clone(),
+        }
+    }
+
+    /// Requests for every level of the dungeon, the selection settings are shared.
+    pub fn requests(&self) -> Vec<GenerationRequest> {
+        let mut requests = vec![self.request()];
+        for depth in self.depths.iter() {
+            requests.push(GenerationRequest {
+                algorithm: depth.algorithm,
+                width: depth.width,
+                height: depth.height,
+                options: depth.options.clone(),
+                ..self.request()
+            });
+        }
+        requests
+    }
+}
+
+/// Reads the presets folder, the presets that can't be read are listed in the panel.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_presets(mut cfg: ResMut<Config>) {
+    let dir = std::path::Path::new(procedural_map::preset::PRESETS_DIR);
+    cfg.presets.clear();
+    let mut errors = vec![];
+    for preset in Preset::load_dir(dir) {
+        match preset {
+            Ok(preset) => cfg.presets.push(preset),
+            Err(err) => errors.push(err),
+        }
+    }
+    cfg.preset_status = errors.join("\n");
+}
+
+/// Generates the map described by the page link, if there's one.
+#[cfg(target_arch = "wasm32")]
+pub fn load_link(mut cmds: Commands, mut cfg: ResMut<Config>) {
+    if let Some(preset) = crate::share::read_link() {
+        cfg.apply_preset(&preset);
+        let requests = cfg.requests();
+        start_generation(&mut cmds, &mut cfg, &requests);
+    }
+}
+
+pub fn draw_ui(
+    mut cmds: Commands,
+    mut egui_ctx: ResMut<EguiContext>,
+    mut cfg: ResMut<Config>,
+    mut overlays: ResMut<OverlaySettings>,
+    analysis: std::option::Option<Res<MapAnalysis>>,
+    dungeon: std::option::Option<Res<CurrentDungeon>>,
+) {
     let width = egui::SidePanel::left("my_side_panel")
         .show(egui_ctx.ctx_mut(), |ui| {
             ui.heading("Dungeon generation");
             ui.add_space(10.);
 
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                ui.label("Presets:");
+                ui.group(|ui| {
+                    let mut loaded = None;
+                    ui.horizontal_wrapped(|ui| {
+                        for preset in cfg.presets.iter() {
+                            if ui.button(&preset.name).clicked() {
+                                loaded = Some(preset.clone());
+                            }
+                        }
+                    });
+                    if let Some(preset) = loaded {
+                        cfg.apply_preset(&preset);
+                        cfg.preset_status = format!("Loaded {}", preset.name);
+                    }
+
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(&mut cfg.preset_name);
+                        if ui.button("Save").clicked() {
+                            // Save the seed of the shown map so the preset builds it again
+                            let preset = cfg.preset(cfg.seed.or(analysis.as_ref().map(|a| a.seed)));
+                            let dir = std::path::Path::new(procedural_map::preset::PRESETS_DIR);
+                            cfg.preset_status = match preset.save(dir) {
+                                Ok(path) => format!("Saved to {}", path.display()),
+                                Err(err) => err,
+                            };
+                            cfg.presets.retain(|p| p.name != preset.name);
+                            cfg.presets.push(preset);
+                            cfg.presets.sort_by(|a, b| a.name.cmp(&b.name));
+                        }
+                    });
+                    if !cfg.preset_status.is_empty() {
+                        ui.label(&cfg.preset_status);
+                    }
+                });
+                ui.add_space(10.);
+            }
+
             {
                 let old_algorithm = cfg.algorithm;
 
@@ -50,14 +324,14 @@ pub fn draw_ui(mut cmds: Commands, mut egui_ctx: ResMut<EguiContext>, mut cfg: R
                     });
 
                 ui.add_space(5.);
-                if cfg.algorithm != Algorithm::None {
-                    ui.label(cfg.algorithm.description());
+                if let Some(description) = cfg.algorithm.description() {
+                    ui.label(description);
                 }
                 ui.add_space(10.);
 
                 if old_algorithm != cfg.algorithm {
                     let alg = cfg.algorithm;
-                    cfg.options = alg.options().to_vec();
+                    cfg.options = alg.options();
                 }
             }
 
@@ -71,6 +345,26 @@ pub fn draw_ui(mut cmds: Commands, mut egui_ctx: ResMut<EguiContext>, mut cfg: R
                     ui.label("Height:");
                     ui.add(egui::Slider::new(&mut cfg.height, 2..=100));
 
+                    draw_seed(ui, "Fixed seed", &mut cfg.seed);
+
+                    ui.label("Snapshots:");
+                    egui::ComboBox::from_id_source("Snapshots")
+                        .selected_text(cfg.snapshots.to_string())
+                        .show_ui(ui, |ui| {
+                            for policy in SnapshotPolicy::all() {
+                                let selected = std::mem::discriminant(&cfg.snapshots)
+                                    == std::mem::discriminant(&policy);
+                                if ui.selectable_label(selected, policy.to_string()).clicked()
+                                    && !selected
+                                {
+                                    cfg.snapshots = policy;
+                                }
+                            }
+                        });
+                    if let SnapshotPolicy::EveryNth(n) = &mut cfg.snapshots {
+                        ui.add(egui::Slider::new(n, 2..=100));
+                    }
+
                     ui.label("Delay:");
                     ui.add(egui::Slider::new(&mut cfg.delay, 1..=1000));
                 });
@@ -86,25 +380,285 @@ pub fn draw_ui(mut cmds: Commands, mut egui_ctx: ResMut<EguiContext>, mut cfg: R
                 ui.label("Algorithm Options:");
 
                 ui.group(|ui| {
-                    for (idx, option) in alg.options().iter().enumerate() {
-                        ui.label(option.name);
-                        ui.add(egui::Slider::new(
-                            &mut cfg.options[idx].value,
-                            option.min..=option.max,
-                        ));
+                    draw_options(ui, alg, &mut cfg.options);
+                });
+            }
+
+            // Builders joining all their rooms have no regions to repair
+            if cfg.algorithm.repairs_regions() {
+                ui.label("Disconnected regions:");
+                ui.group(|ui| {
+                    egui::ComboBox::from_id_source("Repair")
+                        .selected_text(cfg.repair.to_string())
+                        .show_ui(ui, |ui| {
+                            for repair in RegionRepair::all() {
+                                let selected = std::mem::discriminant(&cfg.repair)
+                                    == std::mem::discriminant(&repair);
+                                if ui.selectable_label(selected, repair.to_string()).clicked()
+                                    && !selected
+                                {
+                                    cfg.repair = repair;
+                                }
+                            }
+                        });
+                    if let RegionRepair::Drop(min_size) = &mut cfg.repair {
+                        ui.label("Minimum region size:");
+                        ui.add(egui::Slider::new(min_size, 1..=500));
+                    }
+                });
+            }
+
+            {
+                ui.label("Selection:");
+                ui.group(|ui| {
+                    ui.label("Candidates:");
+                    ui.add(egui::Slider::new(&mut cfg.candidates, 1..=64));
+
+                    ui.label("Fitness:");
+                    egui::ComboBox::from_id_source("Fitness")
+                        .selected_text(cfg.fitness.to_string())
+                        .show_ui(ui, |ui| {
+                            for fitness in Fitness::all() {
+                                // Keep the current target when reselecting the same function
+                                let selected = std::mem::discriminant(&cfg.fitness)
+                                    == std::mem::discriminant(&fitness);
+                                if ui.selectable_label(selected, fitness.to_string()).clicked()
+                                    && !selected
+                                {
+                                    cfg.fitness = fitness;
+                                }
+                            }
+                        });
+                    let (min, max) = cfg.fitness.range();
+                    ui.add(egui::Slider::new(cfg.fitness.value_mut(), min..=max));
+
+                    if let Some(analysis) = analysis.as_ref() {
+                        ui.label(format!("Winning score: {:.3}", analysis.score));
+                        draw_score_distribution(ui, &analysis.scores);
+                    }
+                });
+
+                ui.label("Constraints:");
+                ui.group(|ui| {
+                    for (constraint, enabled) in cfg.constraints.iter_mut() {
+                        ui.checkbox(enabled, constraint.name());
+                        if *enabled {
+                            for (value, min, max) in constraint.values_mut() {
+                                ui.add(egui::Slider::new(value, min..=max));
+                            }
+                        }
+                    }
+
+                    ui.label("Max attempts:");
+                    ui.add(egui::Slider::new(&mut cfg.max_attempts, 1..=100));
+
+                    if let Some(analysis) = analysis.as_ref() {
+                        ui.label(format!("Seed: {}", analysis.seed));
+                        ui.label(format!("Attempts: {}", analysis.attempts));
+                        if analysis.satisfied {
+                            ui.label("Every constraint is met");
+                        } else {
+                            ui.colored_label(egui::Color32::RED, "Some constraints are not met");
+                        }
+                        for (constraint, count) in analysis.failures.iter() {
+                            ui.label(format!("{}: failed {} times", constraint, count));
+                        }
+                        if let Some((constraint, _)) = analysis.most_failed {
+                            ui.label(format!("Most failed: {}", constraint));
+                        }
+                    }
+                });
+            }
+
+            {
+                ui.label("Levels below:");
+                ui.group(|ui| {
+                    let mut removed = None;
+                    for (idx, depth) in cfg.depths.iter_mut().enumerate() {
+                        let title = format!("Depth {}: {}", idx + 2, depth.algorithm);
+                        egui::CollapsingHeader::new(title)
+                            .id_source(("Depth", idx))
+                            .show(ui, |ui| draw_depth(ui, ("Depth", idx), depth));
+                        if ui.small_button("Remove").clicked() {
+                            removed = Some(idx);
+                        }
+                    }
+                    if let Some(idx) = removed {
+                        cfg.depths.remove(idx);
+                    }
+
+                    // New levels start with the settings of the first one
+                    if ui.button("Add level").clicked() {
+                        let depth = cfg.first_level();
+                        cfg.depths.push(depth);
+                    }
+
+                    ui.label("Spawns per room:");
+                    ui.add(egui::Slider::new(&mut cfg.spawns_per_room, 0..=10));
+                    ui.label("Locked doors:");
+                    ui.add(egui::Slider::new(&mut cfg.locks, 0..=5));
+
+                    if let Some(dungeon) = dungeon.as_ref() {
+                        if dungeon.0.len() > 1 {
+                            ui.label("Show level:");
+                            ui.horizontal_wrapped(|ui| {
+                                for level in 0..dungeon.0.len() {
+                                    let selected = cfg.level == level;
+                                    if ui
+                                        .selectable_label(selected, (level + 1).to_string())
+                                        .clicked()
+                                    {
+                                        cfg.level = level;
+                                        cmds.insert_resource(NextState(States::Running));
+                                    }
+                                }
+                            });
+                        }
+
+                        #[cfg(not(target_arch = "wasm32"))]
+                        {
+                            if ui.button("Export dungeon").clicked() {
+                                cfg.export_status = match dungeon.0.save(std::path::Path::new("."))
+                                {
+                                    Ok(path) => format!("Dungeon exported to {}", path.display()),
+                                    Err(err) => err,
+                                };
+                            }
+                            if !cfg.export_status.is_empty() {
+                                ui.label(&cfg.export_status);
+                            }
+                        }
+                    }
+                });
+            }
+
+            {
+                ui.label("Comparison:");
+                ui.group(|ui| {
+                    // The compared map starts with the settings of the first level
+                    let mut enabled = cfg.comparison.is_some();
+                    ui.checkbox(&mut enabled, "Compare with another map");
+                    if !enabled {
+                        cfg.comparison = None;
+                    } else if cfg.comparison.is_none() {
+                        cfg.comparison = Some(ComparisonConfig {
+                            level: cfg.first_level(),
+                            seed: None,
+                        });
+                    }
+
+                    if let Some(comparison) = cfg.comparison.as_mut() {
+                        draw_depth(ui, "Comparison", &mut comparison.level);
+                        draw_seed(ui, "Own seed", &mut comparison.seed);
                     }
                 });
             }
 
+            {
+                // Work on a copy so the overlays are only redrawn when a toggle changes
+                let mut settings = *overlays;
+                ui.label("Overlays:");
+                ui.group(|ui| {
+                    ui.checkbox(&mut settings.partitions, "Partitions");
+                    ui.checkbox(&mut settings.rejected_rooms, "Rejected rooms");
+                    ui.checkbox(&mut settings.room_centers, "Room centers");
+                    ui.checkbox(&mut settings.corridors, "Corridors");
+                    ui.checkbox(&mut settings.head, "Digger position");
+                    ui.checkbox(&mut settings.distances, "Distance to start");
+                    ui.checkbox(&mut settings.areas, "Cave areas");
+                });
+
+                ui.label("Analysis:");
+                ui.group(|ui| {
+                    ui.checkbox(&mut settings.analysis, "Distance heatmap and path");
+                    ui.checkbox(&mut settings.fov, "Field of view of the clicked tile");
+                    if settings.fov {
+                        ui.add(egui::Slider::new(&mut settings.fov_radius, 1..=30).text("radius"));
+                    }
+                    if let Some(result) = analysis.as_ref().and_then(|a| a.analysis.as_ref()) {
+                        if result.path.is_empty() {
+                            ui.label("Exit unreachable");
+                        } else {
+                            ui.label(format!(
+                                "Start to exit: {} steps, cost {:.1}",
+                                result.path.len() - 1,
+                                result.path_cost
+                            ));
+                        }
+                    }
+                });
+
+                if let Some(analysis) = analysis.as_ref() {
+                    let metrics = &analysis.metrics;
+                    ui.label("Metrics:");
+                    ui.group(|ui| {
+                        egui::Grid::new("metrics").show(ui, |ui| {
+                            ui.label("Floor");
+                            ui.label(format!("{:.0}%", metrics.floor_ratio * 100.));
+                            ui.end_row();
+                            ui.label("Regions");
+                            ui.label(metrics.regions.to_string());
+                            ui.end_row();
+                            ui.label("Largest region");
+                            ui.label(format!("{} tiles", metrics.largest_region));
+                            ui.end_row();
+                            ui.label("Dead ends");
+                            ui.label(metrics.dead_ends.to_string());
+                            ui.end_row();
+                            ui.label("Corridors");
+                            ui.label(format!("{:.0}%", metrics.corridor_ratio * 100.));
+                            ui.end_row();
+                            if let Some(size) = metrics.average_room_size {
+                                ui.label("Average room");
+                                ui.label(format!("{:.1} tiles", size));
+                                ui.end_row();
+                            }
+                            if let Some(length) = metrics.path_length {
+                                ui.label("Start to exit");
+                                ui.label(format!("{} steps", length));
+                                ui.end_row();
+                            }
+                            ui.label("Loops");
+                            ui.label(metrics.loops.to_string());
+                            ui.end_row();
+                        });
+                    });
+                }
+
+                if settings != *overlays {
+                    *overlays = settings;
+                }
+            }
+
             ui.with_layout(egui::Layout::bottom_up(egui::Align::Center), |ui| {
                 if ui
-                    .add_enabled(
-                        cfg.algorithm != Algorithm::None,
-                        egui::Button::new("Generate"),
-                    )
+                    .add_enabled(cfg.is_valid(), egui::Button::new("Generate"))
+                    .clicked()
+                {
+                    let requests = cfg.requests();
+                    start_generation(&mut cmds, &mut cfg, &requests);
+                }
+
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(cfg.is_valid(), egui::Button::new("Gallery"))
+                        .clicked()
+                    {
+                        start_gallery(&mut cmds, &cfg);
+                    }
+                    ui.add(egui::Slider::new(&mut cfg.gallery_size, 16..=64).text("maps"));
+                });
+
+                #[cfg(target_arch = "wasm32")]
+                if ui
+                    .add_enabled(analysis.is_some(), egui::Button::new("Copy link"))
                     .clicked()
                 {
-                    cmds.insert_resource(NextState(States::Running));
+                    crate::share::copy_link();
+                }
+
+                if let Some(error) = cfg.error.as_ref() {
+                    ui.colored_label(egui::Color32::RED, error);
                 }
             });
         })
@@ -114,3 +668,400 @@ pub fn draw_ui(mut cmds: Commands, mut egui_ctx: ResMut<EguiContext>, mut cfg: R
 
     cfg.offset = width;
 }
+
+/// Phases of the shown level with the label of the current frame, clicking a phase plays it
+/// again.
+pub fn draw_timeline(
+    mut cmds: Commands,
+    mut egui_ctx: ResMut<EguiContext>,
+    #[cfg(not(target_arch = "wasm32"))] mut cfg: ResMut<Config>,
+    timeline: std::option::Option<ResMut<Timeline>>,
+    comparison: std::option::Option<ResMut<ComparisonView>>,
+    state: Res<CurrentState<States>>,
+) {
+    let mut timeline = match timeline {
+        Some(timeline) => timeline,
+        None => return,
+    };
+
+    egui::Window::new("Timeline").show(egui_ctx.ctx_mut(), |ui| {
+        ui.label(format!(
+            "Frame {} of {}",
+            timeline.next,
+            timeline.frames.len()
+        ));
+        if let Some(frame) = timeline.current() {
+            ui.label(format!("Phase: {}", frame.phase));
+            ui.label(format!("Step: {}", frame.label));
+            if let Some(note) = frame.note.as_ref() {
+                ui.label(note);
+            }
+        }
+        ui.separator();
+
+        let current = timeline.next.saturating_sub(1);
+        let mut jump = None;
+        for (phase, frames) in phases(&timeline.frames) {
+            let text = format!("{} ({} frames)", phase, frames.len());
+            if ui
+                .selectable_label(frames.contains(&current), text)
+                .clicked()
+            {
+                jump = Some(frames.start);
+            }
+        }
+        if let Some(frame) = jump {
+            timeline.next = frame;
+            // The compared map jumps to the same frame, or to its last one when shorter
+            if let Some(mut comparison) = comparison {
+                let last = comparison.timeline.frames.len().saturating_sub(1);
+                comparison.timeline.next = frame.min(last);
+            }
+            if matches!(state.0, States::Menu | States::Play) {
+                cmds.insert_resource(NextState(States::Replaying));
+            }
+        }
+
+        // Frames are shown as long as in the app
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            ui.separator();
+            ui.horizontal(|ui| {
+                for path in ["playback.gif", "playback.png"] {
+                    if ui.button(format!("Export {}", path)).clicked() {
+                        let path = std::path::Path::new(path);
+                        cfg.playback_status =
+                            match export_animation(&timeline.frames, cfg.delay, path) {
+                                Ok(()) => format!("Playback exported to {}", path.display()),
+                                Err(err) => err,
+                            };
+                    }
+                }
+                if !cfg.playback_status.is_empty() {
+                    ui.label(&cfg.playback_status);
+                }
+            });
+        }
+    });
+}
+
+/// Starts and stops walking the generated map, with how much of it has been seen.
+pub fn draw_play(
+    mut cmds: Commands,
+    mut egui_ctx: ResMut<EguiContext>,
+    analysis: std::option::Option<Res<MapAnalysis>>,
+    player: std::option::Option<Res<Player>>,
+    state: Res<CurrentState<States>>,
+) {
+    if analysis.is_none_or(|analysis| analysis.map.start.is_none()) {
+        return;
+    }
+
+    egui::Window::new("Play").show(egui_ctx.ctx_mut(), |ui| {
+        if state.0 != States::Play {
+            let play = egui::Button::new("Walk the map");
+            if ui.add_enabled(state.0 == States::Menu, play).clicked() {
+                cmds.insert_resource(NextState(States::Play));
+            }
+            return;
+        }
+
+        ui.label("Arrows or WASD to move, Q, E, Z and C diagonally");
+        if let Some(player) = player.as_ref() {
+            ui.label(format!("Moves: {}", player.moves));
+            ui.label(format!("Keys: {}", player.keys()));
+            ui.label(format!("Explored: {:.0}%", player.explored_ratio() * 100.));
+        }
+        if ui.button("Stop (Escape)").clicked() {
+            cmds.insert_resource(NextState(States::Menu));
+        }
+    });
+}
+
+/// Thumbnails of the gallery, clicking one generates its seed with the usual playback.
+///
+/// The maps still missing are generated a few at a time, the ones that fail are counted and
+/// skipped.
+pub fn draw_gallery(
+    mut cmds: Commands,
+    mut egui_ctx: ResMut<EguiContext>,
+    mut cfg: ResMut<Config>,
+    gallery: std::option::Option<ResMut<Gallery>>,
+) {
+    let mut gallery = match gallery {
+        Some(gallery) => gallery,
+        None => return,
+    };
+    for _ in 0..gallery.remaining.min(GALLERY_MAPS_PER_FRAME) {
+        gallery.remaining -= 1;
+        let request = GenerationRequest {
+            seed: rand::random(),
+            ..gallery.request.clone()
+        };
+        match generate(&request) {
+            Ok(generation) => {
+                let map = generation.builder.get_map();
+                let image = gallery.atlas.thumbnail(&map, THUMBNAIL_SIZE);
+                let size = [image.width() as usize, image.height() as usize];
+                let image = egui::ColorImage::from_rgba_unmultiplied(size, image.as_raw());
+                let name = format!("Seed {}", generation.seed);
+                let texture =
+                    egui_ctx
+                        .ctx_mut()
+                        .load_texture(name, image, egui::TextureFilter::Linear);
+                gallery.thumbnails.push((generation.seed, texture));
+            }
+            Err(err) => {
+                gallery.failed += 1;
+                gallery.error = Some(err.to_string());
+            }
+        }
+    }
+
+    let mut open = true;
+    let mut picked = None;
+    egui::Window::new("Gallery")
+        .open(&mut open)
+        .vscroll(true)
+        .show(egui_ctx.ctx_mut(), |ui| {
+            if gallery.remaining > 0 {
+                let total = gallery.thumbnails.len() + gallery.failed + gallery.remaining;
+                ui.label(format!(
+                    "Generating {} maps, {} left",
+                    total, gallery.remaining
+                ));
+            }
+            if let Some(error) = gallery.error.as_ref() {
+                let message = format!("{} maps failed: {}", gallery.failed, error);
+                ui.colored_label(egui::Color32::RED, message);
+            }
+            egui::Grid::new("gallery").show(ui, |ui| {
+                for (idx, (seed, texture)) in gallery.thumbnails.iter().enumerate() {
+                    let thumbnail = egui::ImageButton::new(texture.id(), texture.size_vec2());
+                    if ui
+                        .add(thumbnail)
+                        .on_hover_text(format!("Seed {}", seed))
+                        .clicked()
+                    {
+                        picked = Some(*seed);
+                    }
+                    if (idx + 1) % GALLERY_COLUMNS == 0 {
+                        ui.end_row();
+                    }
+                }
+            });
+        });
+    if !open {
+        cmds.remove_resource::<Gallery>();
+    }
+
+    if let Some(seed) = picked {
+        // The clicked map is built again as it was: its kept seed, a single candidate and no
+        // retries, the seed setting of the panel is left alone
+        let mut requests = cfg.requests();
+        requests[0] = GenerationRequest {
+            seed,
+            candidates: 1,
+            max_attempts: 1,
+            snapshots: cfg.snapshots,
+            ..gallery.request.clone()
+        };
+        start_generation(&mut cmds, &mut cfg, &requests);
+    }
+}
+
+/// Metrics of the shown level next to the ones of the compared map, with their difference.
+pub fn draw_comparison(
+    mut egui_ctx: ResMut<EguiContext>,
+    analysis: std::option::Option<Res<MapAnalysis>>,
+    comparison: std::option::Option<Res<ComparisonView>>,
+) {
+    let (analysis, comparison) = match (analysis, comparison) {
+        (Some(analysis), Some(comparison)) => (analysis, comparison),
+        _ => return,
+    };
+
+    egui::Window::new("Comparison").show(egui_ctx.ctx_mut(), |ui| {
+        egui::Grid::new("comparison").striped(true).show(ui, |ui| {
+            ui.label("");
+            ui.label("Shown level");
+            ui.label("Compared map");
+            ui.label("Difference");
+            ui.end_row();
+
+            ui.label("Seed");
+            ui.label(analysis.seed.to_string());
+            ui.label(comparison.seed.to_string());
+            ui.end_row();
+
+            let values = metric_values(&analysis.metrics)
+                .into_iter()
+                .zip(metric_values(&comparison.metrics));
+            for ((name, shown, unit), (_, compared, _)) in values {
+                ui.label(name);
+                ui.label(format_metric(shown, unit));
+                ui.label(format_metric(compared, unit));
+                let difference = match (shown, compared) {
+                    (Some(shown), Some(compared)) => format!("{:+.1}{}", compared - shown, unit),
+                    _ => "-".to_string(),
+                };
+                ui.label(difference);
+                ui.end_row();
+            }
+        });
+    });
+}
+
+/// Every metric as a number with its unit, `None` when it's unknown.
+fn metric_values(
+    metrics: &MapMetrics,
+) -> Vec<(&'static str, std::option::Option<f32>, &'static str)> {
+    vec![
+        ("Floor", Some(metrics.floor_ratio * 100.), "%"),
+        ("Regions", Some(metrics.regions as f32), ""),
+        (
+            "Largest region",
+            Some(metrics.largest_region as f32),
+            " tiles",
+        ),
+        ("Dead ends", Some(metrics.dead_ends as f32), ""),
+        ("Corridors", Some(metrics.corridor_ratio * 100.), "%"),
+        ("Average room", metrics.average_room_size, " tiles"),
+        ("Rooms", Some(metrics.rooms as f32), ""),
+        ("Dead end rooms", Some(metrics.dead_end_rooms as f32), ""),
+        (
+            "Start to exit",
+            metrics.path_length.map(|length| length as f32),
+            " steps",
+        ),
+        ("Loops", Some(metrics.loops as f32), ""),
+    ]
+}
+
+fn format_metric(value: std::option::Option<f32>, unit: &str) -> String {
+    value.map_or("-".to_string(), |value| {
+        let value = format!("{:.1}", value);
+        format!("{}{}", value.trim_end_matches(".0"), unit)
+    })
+}
+
+/// Generates the dungeon and shows its first level, or keeps the error to show it in the panel.
+fn start_generation(cmds: &mut Commands, cfg: &mut Config, requests: &[GenerationRequest]) {
+    let comparison = cfg
+        .comparison_request(requests[0].seed)
+        .map(|request| generate(&request))
+        .transpose();
+    match (Dungeon::generate(requests), comparison) {
+        (Ok(mut dungeon), Ok(comparison)) => {
+            if cfg.spawns_per_room > 0 {
+                dungeon.spawn(&SpawnTable::default(), cfg.spawns_per_room);
+            }
+            // Keys are placed after the spawns so they never share a tile
+            if cfg.locks > 0 {
+                dungeon.lock(cfg.locks);
+            }
+            cfg.error = None;
+            cfg.level = 0;
+            // The link holds the seed kept by the selection, which builds the same map alone
+            #[cfg(target_arch = "wasm32")]
+            crate::share::update_link(&cfg.preset(Some(dungeon.levels[0].generation.seed)));
+            cmds.insert_resource(CurrentDungeon(dungeon));
+            match comparison {
+                Some(comparison) => cmds.insert_resource(CurrentComparison(comparison)),
+                None => cmds.remove_resource::<CurrentComparison>(),
+            }
+            cmds.insert_resource(NextState(States::Running));
+        }
+        (Err(err), _) => cfg.error = Some(err.to_string()),
+        (_, Err(err)) => cfg.error = Some(format!("Compared map: {}", err)),
+    }
+}
+
+/// Opens the gallery, its maps are the first level with random seeds, built without snapshots.
+fn start_gallery(cmds: &mut Commands, cfg: &Config) {
+    cmds.insert_resource(Gallery {
+        request: GenerationRequest {
+            snapshots: SnapshotPolicy::Off,
+            ..cfg.request()
+        },
+        atlas: Atlas::default(),
+        remaining: cfg.gallery_size,
+        thumbnails: vec![],
+        failed: 0,
+        error: None,
+    });
+}
+
+/// Checkbox using a fixed seed instead of a random one, along with the seed once checked.
+fn draw_seed(ui: &mut egui::Ui, label: &str, seed: &mut std::option::Option<u64>) {
+    let mut fixed = seed.is_some();
+    ui.checkbox(&mut fixed, label);
+    if fixed {
+        let mut value = seed.unwrap_or_default();
+        ui.add(egui::DragValue::new(&mut value));
+        *seed = Some(value);
+    } else {
+        *seed = None;
+    }
+}
+
+/// Algorithm, size and options of a level below the first one or of the compared map.
+fn draw_depth(ui: &mut egui::Ui, id: impl std::hash::Hash, depth: &mut DepthConfig) {
+    let old_algorithm = depth.algorithm;
+    egui::ComboBox::from_id_source((id, "Algorithm"))
+        .selected_text(depth.algorithm.to_string())
+        .show_ui(ui, |ui| {
+            for alg in Algorithm::all() {
+                ui.selectable_value(&mut depth.algorithm, alg, alg.to_string());
+            }
+        });
+    if old_algorithm != depth.algorithm {
+        depth.options = depth.algorithm.options();
+    }
+
+    ui.label("Width:");
+    ui.add(egui::Slider::new(&mut depth.width, 2..=100));
+    ui.label("Height:");
+    ui.add(egui::Slider::new(&mut depth.height, 2..=100));
+
+    draw_options(ui, depth.algorithm, &mut depth.options);
+}
+
+/// Sliders generated from the option declarations, followed by the validation errors.
+fn draw_options(ui: &mut egui::Ui, algorithm: Algorithm, options: &mut [Option]) {
+    for option in options.iter_mut() {
+        let range = option.min..=option.max;
+        ui.label(option.name);
+        ui.add(egui::Slider::new(&mut option.value, range).suffix(option.unit.suffix()));
+    }
+    for error in algorithm.validate(options) {
+        ui.colored_label(egui::Color32::RED, error);
+    }
+}
+
+/// Histogram of the candidate scores.
+fn draw_score_distribution(ui: &mut egui::Ui, scores: &[f32]) {
+    const BINS: usize = 10;
+    let mut counts = [0; BINS];
+    for score in scores {
+        let bin = (score.clamp(0., 1.) * BINS as f32) as usize;
+        counts[bin.min(BINS - 1)] += 1;
+    }
+
+    let bars = counts
+        .iter()
+        .enumerate()
+        .map(|(bin, count)| {
+            egui::plot::Bar::new((bin as f64 + 0.5) / BINS as f64, *count as f64)
+                .width(1. / BINS as f64)
+        })
+        .collect();
+
+    egui::plot::Plot::new("scores")
+        .height(80.)
+        .allow_drag(false)
+        .allow_zoom(false)
+        .allow_scroll(false)
+        .allow_boxed_zoom(false)
+        .show(ui, |plot| plot.bar_chart(egui::plot::BarChart::new(bars)));
+}
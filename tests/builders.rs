@@ -0,0 +1,290 @@
+use procedural_map::{
+    algorithms::{Algorithm, Option},
+    analysis::regions,
+    fov::field_of_view,
+    generator::{generate, Fitness, GenerationRequest},
+    map::{Map, TileType},
+    map_builders::{voronoi_areas, MapBuilder, RegionRepair, SnapshotPolicy},
+    puzzle::{place_locks, solve},
+    room_graph::RoomGraph,
+    spawner::{spawn, spawn_areas, SpawnTable},
+};
+use proptest::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
+
+/// An algorithm, a map size, a seed and valid option values to build a map with.
+#[derive(Debug, Clone)]
+struct BuildParams {
+    /// Index of the algorithm in `Algorithm::all`.
+    alg: usize,
+    width: i32,
+    height: i32,
+    seed: u64,
+    values: Vec<i32>,
+}
+
+impl BuildParams {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::all()[self.alg]
+    }
+
+    fn options(&self) -> Vec<Option> {
+        let mut options = self.algorithm().options();
+        for (option, value) in options.iter_mut().zip(&self.values) {
+            option.value = *value;
+        }
+        options
+    }
+
+    /// Builder once the map is built, `None` when the builder reports the size as impossible.
+    fn build(&self) -> std::option::Option<Box<dyn MapBuilder>> {
+        let mut builder = self.algorithm().get(&self.options()).ok()?;
+        builder.build_map(self.width, self.height, self.seed).ok()?;
+        Some(builder)
+    }
+}
+
+fn build_params() -> impl Strategy<Value = BuildParams> {
+    (0..Algorithm::all().len())
+        .prop_flat_map(|alg| {
+            let ranges: Vec<_> = Algorithm::all()[alg]
+                .options()
+                .iter()
+                .map(|option| option.min..=option.max)
+                .collect();
+            (Just(alg), 1..=80i32, 1..=60i32, any::<u64>(), ranges)
+        })
+        .prop_map(|(alg, width, height, seed, values)| BuildParams {
+            alg,
+            width,
+            height,
+            seed,
+            values,
+        })
+        .prop_filter("options must be valid", |params| {
+            params.algorithm().validate(&params.options()).is_empty()
+        })
+}
+
+fn is_border(map: &Map, idx: usize) -> bool {
+    let (x, y) = map.idx_xy(idx);
+    x == 0 || y == 0 || x == map.width - 1 || y == map.height - 1
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(128))]
+
+    #[test]
+    fn built_maps_are_complete(params in build_params()) {
+        // Impossible sizes are reported as errors, they must never panic
+        let builder = match params.build() {
+            Some(builder) => builder,
+            None => return Ok(()),
+        };
+        let (width, height) = (params.width, params.height);
+
+        let map = builder.get_map();
+        prop_assert_eq!(map.tiles.len(), (width * height) as usize);
+        prop_assert!(map.tiles.iter().all(|tile| tile.is_some()));
+        for idx in (0..map.tiles.len()).filter(|idx| is_border(&map, *idx)) {
+            prop_assert_eq!(map.tiles[idx].clone(), Some(TileType::Wall));
+        }
+
+        let snapshots = builder.get_snapshot_history();
+        prop_assert!(!snapshots.is_empty());
+        for snapshot in snapshots.iter() {
+            prop_assert_eq!((snapshot.map.width, snapshot.map.height), (width, height));
+            prop_assert_eq!(snapshot.map.tiles.len(), map.tiles.len());
+        }
+    }
+
+    #[test]
+    fn built_maps_are_connected(params in build_params()) {
+        // Builders repair their regions themselves, without going through `generate`
+        let builder = match params.build() {
+            Some(builder) => builder,
+            None => return Ok(()),
+        };
+        prop_assert!(regions(&builder.get_map(), true).len() <= 1);
+    }
+
+    #[test]
+    fn building_again_starts_from_scratch(params in build_params(), other_seed in any::<u64>()) {
+        let fresh = match params.build() {
+            Some(builder) => builder,
+            None => return Ok(()),
+        };
+        // The same builder first builds another map, bigger than the one compared
+        let mut builder = params.algorithm().get(&params.options()).unwrap();
+        let _ = builder.build_map(80, 60, other_seed);
+        builder.build_map(params.width, params.height, params.seed).unwrap();
+
+        let (map, expected) = (builder.get_map(), fresh.get_map());
+        prop_assert_eq!(map.tiles, expected.tiles);
+        prop_assert_eq!((map.start, map.exit), (expected.start, expected.exit));
+        prop_assert_eq!(
+            builder.get_rooms().map(|rooms| rooms.len()),
+            fresh.get_rooms().map(|rooms| rooms.len())
+        );
+        let labels = |builder: &dyn MapBuilder| -> Vec<String> {
+            builder.get_snapshot_history().iter().map(|s| s.label.clone()).collect()
+        };
+        prop_assert_eq!(labels(builder.as_ref()), labels(fresh.as_ref()));
+    }
+
+    #[test]
+    fn joined_maps_are_connected(params in build_params()) {
+        let request = GenerationRequest {
+            algorithm: params.algorithm(),
+            width: params.width,
+            height: params.height,
+            options: params.options(),
+            repair: RegionRepair::Join,
+            seed: params.seed,
+            candidates: 1,
+            fitness: Fitness::default(),
+            constraints: vec![],
+            max_attempts: 1,
+            snapshots: SnapshotPolicy::Off,
+        };
+        let generation = match generate(&request) {
+            Ok(generation) => generation,
+            Err(_) => return Ok(()),
+        };
+
+        let map = generation.builder.get_map();
+        prop_assert!(regions(&map, true).len() <= 1);
+        if let (Some(start), Some(exit)) = (map.start, map.exit) {
+            prop_assert!(map.is_walkable(start));
+            prop_assert!(map.is_walkable(exit));
+            prop_assert!(!procedural_map::analysis::find_path(&map, start, exit).is_empty());
+        }
+    }
+
+    #[test]
+    fn field_of_view_is_symmetric(params in build_params()) {
+        let builder = match params.build() {
+            Some(builder) => builder,
+            None => return Ok(()),
+        };
+
+        let map = builder.get_map();
+        let origin = match (0..map.tiles.len()).find(|idx| map.is_walkable(*idx)) {
+            Some(origin) => origin,
+            None => return Ok(()),
+        };
+        let visible = field_of_view(&map, origin, 8);
+        for idx in visible.iter().copied().filter(|idx| map.is_walkable(*idx)) {
+            prop_assert!(field_of_view(&map, idx, 8).contains(&origin));
+        }
+    }
+
+    #[test]
+    fn spawns_follow_the_rules(
+        params in build_params(),
+        depth in 1..=10i32,
+        max_per_area in 0..=5usize,
+    ) {
+        let builder = match params.build() {
+            Some(builder) => builder,
+            None => return Ok(()),
+        };
+
+        let map = builder.get_map();
+        let areas = spawn_areas(&map, builder.get_rooms().as_deref());
+        let mut rng = StdRng::seed_from_u64(params.seed);
+        let spawns = spawn(&map, &areas, &SpawnTable::default(), depth, max_per_area, &mut rng);
+        for spawn in spawns.iter() {
+            prop_assert_eq!(map.tiles[spawn.idx].clone(), Some(TileType::Floor));
+            prop_assert!(Some(spawn.idx) != map.start && Some(spawn.idx) != map.exit);
+            prop_assert_eq!(spawns.iter().filter(|other| other.idx == spawn.idx).count(), 1);
+        }
+        for area in areas.iter() {
+            let count = spawns.iter().filter(|spawn| area.contains(&spawn.idx)).count();
+            prop_assert!(count <= max_per_area);
+            if map.start.is_some_and(|start| area.contains(&start)) {
+                prop_assert_eq!(count, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn room_graph_matches_the_tiles(params in build_params()) {
+        let builder = match params.build() {
+            Some(builder) => builder,
+            None => return Ok(()),
+        };
+
+        let map = builder.get_map();
+        let graph = RoomGraph::new(&map, builder.get_rooms().as_deref());
+        for (room, node) in graph.nodes.iter().enumerate() {
+            for idx in node.tiles.iter() {
+                prop_assert!(map.is_walkable(*idx));
+                prop_assert_eq!(graph.room_of[*idx], Some(room));
+            }
+        }
+        let in_rooms = graph.room_of.iter().flatten().count();
+        prop_assert_eq!(in_rooms, graph.nodes.iter().map(|node| node.tiles.len()).sum::<usize>());
+        for edge in graph.edges.iter() {
+            prop_assert!(edge.from < edge.to && edge.to < graph.nodes.len());
+            for idx in edge.corridor.iter() {
+                prop_assert!(map.is_walkable(*idx) && graph.room_of[*idx].is_none());
+            }
+        }
+        for leaf in graph.leaves() {
+            prop_assert_eq!(graph.neighbors(leaf).len(), 1);
+        }
+    }
+
+    #[test]
+    fn locked_levels_can_be_solved(
+        params in build_params(),
+        max_locks in 0..=5usize,
+    ) {
+        let builder = match params.build() {
+            Some(builder) => builder,
+            None => return Ok(()),
+        };
+        let mut map = builder.get_map();
+        let graph = RoomGraph::new(&map, builder.get_rooms().as_deref());
+        let mut rng = StdRng::seed_from_u64(params.seed);
+        let placed = place_locks(&mut map, &graph, max_locks, &mut rng);
+        prop_assert!(placed <= max_locks);
+        prop_assert_eq!(map.locks.len(), placed);
+        for lock in map.locks.iter() {
+            prop_assert_eq!(map.tiles[lock.door].clone(), Some(TileType::Door { open: false }));
+            prop_assert_eq!(map.tiles[lock.key].clone(), Some(TileType::Floor));
+        }
+        if map.start.is_some() && map.exit.is_some() {
+            prop_assert!(solve(&map).is_some());
+        }
+    }
+
+    #[test]
+    fn areas_are_connected_and_cover_the_floor(
+        params in build_params(),
+        area_size in 1..=100usize,
+    ) {
+        let builder = match params.build() {
+            Some(builder) => builder,
+            None => return Ok(()),
+        };
+
+        let map = builder.get_map();
+        let areas = voronoi_areas(&map, area_size, &mut StdRng::seed_from_u64(params.seed));
+        for (idx, id) in areas.iter().enumerate() {
+            prop_assert_eq!(id.is_some(), map.is_walkable(idx));
+        }
+        // Every area is a single region of the map restricted to its own tiles
+        let count = areas.iter().flatten().max().map_or(0, |id| id + 1);
+        for id in 0..count {
+            let mut area = map.clone();
+            for (idx, tile) in area.tiles.iter_mut().enumerate() {
+                if areas[idx] != Some(id) {
+                    *tile = Some(TileType::Wall);
+                }
+            }
+            prop_assert_eq!(regions(&area, false).len(), 1);
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use std::{env, fs, path::PathBuf};
+
+use procedural_map::algorithms::Algorithm;
+
+/// Seeded maps of every algorithm are compared with the files in `tests/golden`.
+///
+/// Run with `UPDATE_GOLDEN=1` to write the files again after an intended change.
+#[test]
+fn seeded_maps_match_golden_files() {
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+
+    let mut mismatches = vec![];
+    for algorithm in Algorithm::all() {
+        for seed in [1, 42] {
+            let mut builder = algorithm.get(&algorithm.options()).unwrap();
+            builder
+                .build_map(60, 40, seed)
+                .expect("Default options build a map");
+            let map = builder.get_map();
+
+            let mut output = map.rows().join("\n");
+            output += &format!(
+                "\nstart: {:?}\nexit: {:?}\n",
+                map.start.map(|idx| map.idx_xy(idx)),
+                map.exit.map(|idx| map.idx_xy(idx))
+            );
+
+            let path = dir.join(format!("{}-{}.txt", algorithm.key(), seed));
+            if update {
+                fs::write(&path, output).unwrap();
+            } else if fs::read_to_string(&path).ok().as_ref() != Some(&output) {
+                mismatches.push(path.display().to_string());
+            }
+        }
+    }
+
+    assert!(
+        mismatches.is_empty(),
+        "Maps differ from {}, run with UPDATE_GOLDEN=1 if the change is intended",
+        mismatches.join(", ")
+    );
+}